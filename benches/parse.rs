@@ -0,0 +1,84 @@
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use httpfromtcp::headers::Headers;
+use httpfromtcp::request::{RequestReader, request_from_reader};
+
+const SMALL_REQUEST: &[u8] = b"GET /coffee?size=large HTTP/1.1\r\nHost: localhost:42069\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\n\r\n";
+
+// Something closer to what a browser sends, plus a pile of custom headers.
+fn large_request() -> Vec<u8> {
+    let mut request = b"POST /api/v1/orders/12345/items?expand=true&fields=name,price HTTP/1.1\r\n\
+        Host: shop.example.com\r\n\
+        User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+        Accept: text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8\r\n\
+        Accept-Language: en-US,en;q=0.5\r\n\
+        Accept-Encoding: gzip, deflate, br, zstd\r\n\
+        Cookie: session=8f14e45fceea167a5a36dedd4bea2543; theme=dark; consent=yes\r\n\
+        Content-Type: application/json\r\n"
+        .to_vec();
+    for i in 0..40 {
+        request.extend_from_slice(format!("X-Custom-Header-{}: value-{}\r\n", i, i).as_bytes());
+    }
+    request.extend_from_slice(b"Content-Length: 27\r\n\r\n{\"item\":\"coffee\",\"qty\":2}\r\n");
+    request
+}
+
+// A bare request line in front, so the section is read the way the server
+// reads it rather than through `Headers::parse`, which copies its input.
+// HTTP/1.0, so that the 50 headers needn't include a `Host`.
+fn header_section() -> Vec<u8> {
+    let mut section = b"GET / HTTP/1.0\r\n".to_vec();
+    for i in 0..50 {
+        section.extend_from_slice(
+            format!("X-Header-{}: some moderately long value {}\r\n", i, i).as_bytes(),
+        );
+    }
+    section.extend_from_slice(b"\r\n");
+    section
+}
+
+fn bench_requests(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let large = large_request();
+
+    let mut group = c.benchmark_group("request");
+    group.throughput(Throughput::Bytes(SMALL_REQUEST.len() as u64));
+    group.bench_function("small", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { request_from_reader(SMALL_REQUEST).await.unwrap() })
+    });
+    group.throughput(Throughput::Bytes(large.len() as u64));
+    group.bench_function("large", |b| {
+        b.to_async(&runtime)
+            .iter(|| async { request_from_reader(&large[..]).await.unwrap() })
+    });
+    group.finish();
+}
+
+fn bench_headers(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let section = header_section();
+
+    let mut group = c.benchmark_group("headers");
+    group.throughput(Throughput::Bytes(section.len() as u64));
+    group.bench_function("parse_50", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let mut reader = RequestReader::new(&section[..]);
+            reader.next_request().await.unwrap().unwrap().headers
+        })
+    });
+    group.bench_function("lookup", |b| {
+        let headers: Headers = runtime
+            .block_on(request_from_reader(&section[..]))
+            .unwrap()
+            .headers;
+        b.iter(|| headers.get("x-header-49"))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_requests, bench_headers);
+criterion_main!(benches);
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::headers::{Headers, find_crlf};
+use crate::request::{self, Decoder, Framing, HeaderSize, HttpVersion, Limits};
+use crate::response::StatusCode;
+
+const READ_SIZE: usize = 8 * 1024;
+
+// Bodies are streamed through, so only the head is bounded.
+const LIMITS: Limits = Limits {
+    max_request_line: 8 * 1024,
+    max_header_size: 64 * 1024,
+    max_header_count: 100,
+    max_body_size: usize::MAX,
+};
+
+fn error_malformed(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    future: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    match timeout {
+        Some(t) => match tokio::time::timeout(t, future).await {
+            Ok(res) => res,
+            Err(_) => Err(Error::from(ErrorKind::TimedOut)),
+        },
+        None => future.await,
+    }
+}
+
+// The server dropped the connection rather than timing out or answering.
+pub(crate) fn is_reset(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe
+    )
+}
+
+pub(crate) fn asks_close(headers: &Headers) -> bool {
+    headers.get_joined("connection").is_some_and(|value| {
+        value
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case("close"))
+    })
+}
+
+// e.g. : HTTP/1.1 404 Not Found
+fn parse_status_line(line: &[u8]) -> Result<(HttpVersion, StatusCode), Error> {
+    let mut parts = line.splitn(3, |b| *b == b' ');
+    let version = match parts.next().and_then(request::parse_http_version) {
+        Some((1, 0)) => HttpVersion::Http10,
+        Some((1, _)) => HttpVersion::Http11,
+        _ => return Err(error_malformed("Malformed Status Line")),
+    };
+
+    let code = match parts.next() {
+        Some(code) if code.len() == 3 && code.iter().all(u8::is_ascii_digit) => code
+            .iter()
+            .fold(0, |n, digit| n * 10 + (digit - b'0') as u16),
+        _ => return Err(error_malformed("Malformed Status Line")),
+    };
+    let status_code = match StatusCode::from_u16(code) {
+        StatusCode::Custom(code, _) => {
+            let reason = String::from_utf8_lossy(parts.next().unwrap_or_default());
+            StatusCode::Custom(code, reason.into_owned())
+        }
+        status_code => status_code,
+    };
+
+    Ok((version, status_code))
+}
+
+pub(crate) struct ResponseHead {
+    pub(crate) version: HttpVersion,
+    pub(crate) status_code: StatusCode,
+    pub(crate) headers: Headers,
+    // Bytes of the head and how much of the header limits it used, for the
+    // decoder.
+    parsed: usize,
+    header_size: HeaderSize,
+}
+
+impl ResponseHead {
+    // RFC 9112 section 6.3, minus the leniency: a response that could be read
+    // two ways is refused rather than guessed at.
+    pub(crate) fn framing(&self, head_request: bool) -> Result<Framing, Error> {
+        let code = self.status_code.code();
+        let framing =
+            if head_request || self.status_code.is_informational() || code == 204 || code == 304 {
+                Framing::Length(0)
+            } else if self.headers.contains("transfer-encoding")
+                && self.headers.contains("content-length")
+            {
+                return Err(error_malformed(
+                    "Both Transfer-Encoding and Content-Length Present",
+                ));
+            } else if request::is_chunked(&self.headers, self.version, self.parsed)? {
+                Framing::Chunked
+            } else {
+                match request::content_length(&self.headers, self.parsed)? {
+                    Some(n) => Framing::Length(n),
+                    None => Framing::Close,
+                }
+            };
+
+        Ok(framing)
+    }
+
+    pub(crate) fn decoder(&self, framing: Framing) -> Decoder {
+        Decoder::new(framing, LIMITS, self.parsed, self.header_size)
+    }
+
+    // Whether the connection can carry another request once this response
+    // is read.
+    pub(crate) fn keep_alive(&self) -> bool {
+        self.version == HttpVersion::Http11 && !asks_close(&self.headers)
+    }
+}
+
+/// One connection to a server, with whatever it sent that hasn't been
+/// parsed yet.
+pub(crate) struct Connection {
+    stream: TcpStream,
+    buffer: BytesMut,
+    idle_since: Instant,
+}
+
+impl Connection {
+    // An idle connection the server closed, or sent something on unasked,
+    // can't be used again.
+    fn is_open(&self) -> bool {
+        if !self.buffer.is_empty() {
+            return false;
+        }
+        let mut byte = [0u8; 1];
+        matches!(self.stream.try_read(&mut byte), Err(e) if e.kind() == ErrorKind::WouldBlock)
+    }
+
+    pub(crate) async fn write_all(
+        &mut self,
+        data: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<(), Error> {
+        with_timeout(timeout, self.stream.write_all(data)).await
+    }
+
+    async fn fill(&mut self, timeout: Option<Duration>) -> Result<usize, Error> {
+        self.buffer.reserve(READ_SIZE);
+        with_timeout(timeout, self.stream.read_buf(&mut self.buffer)).await
+    }
+
+    /// Reads the status line and headers of the next response. `None` if the
+    /// connection was closed or reset before any of it arrived.
+    pub(crate) async fn read_head(
+        &mut self,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ResponseHead>, Error> {
+        let mut status = None;
+        let mut headers = Headers::new();
+        let mut header_size = HeaderSize::default();
+        let mut parsed = 0;
+
+        loop {
+            if status.is_none() {
+                match find_crlf(&self.buffer) {
+                    Some(i) => {
+                        status = Some(parse_status_line(&self.buffer[..i])?);
+                        self.buffer.advance(i + 2);
+                        parsed += i + 2;
+                    }
+                    None if self.buffer.len() > LIMITS.max_request_line => {
+                        return Err(error_malformed("Status Line Too Long"));
+                    }
+                    None => {}
+                }
+            }
+
+            if let Some((version, status_code)) = &status {
+                let len = self.buffer.len();
+                let done = header_size.parse_fields(&mut headers, &LIMITS, &mut self.buffer)?;
+                parsed += len - self.buffer.len();
+
+                if done {
+                    return Ok(Some(ResponseHead {
+                        version: *version,
+                        status_code: status_code.clone(),
+                        headers,
+                        parsed,
+                        header_size,
+                    }));
+                }
+            }
+
+            let nothing_yet = parsed == 0 && self.buffer.is_empty();
+            let n = match self.fill(timeout).await {
+                Ok(n) => n,
+                Err(e) if nothing_yet && is_reset(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                if nothing_yet {
+                    return Ok(None);
+                }
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Server closed the connection mid-response",
+                ));
+            }
+        }
+    }
+
+    /// The next piece of the response body, `None` once it is done.
+    pub(crate) async fn read_body(
+        &mut self,
+        decoder: &mut Decoder,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Bytes>, Error> {
+        loop {
+            if let Some(data) = decoder.decode(&mut self.buffer, usize::MAX)? {
+                return Ok(Some(data));
+            }
+            if decoder.is_done() {
+                return Ok(None);
+            }
+
+            if self.fill(timeout).await? == 0 {
+                if decoder.close() {
+                    return Ok(None);
+                }
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "Server closed the connection mid-body",
+                ));
+            }
+        }
+    }
+}
+
+/// Keeps connections open between requests, by the `host:port` they go to.
+#[derive(Clone)]
+pub(crate) struct Pool {
+    pub(crate) max_idle: usize,
+    pub(crate) idle_timeout: Option<Duration>,
+    idle: Arc<Mutex<HashMap<String, Vec<Connection>>>>,
+}
+
+impl Pool {
+    pub(crate) fn new(max_idle: usize, idle_timeout: Option<Duration>) -> Pool {
+        Pool {
+            max_idle,
+            idle_timeout,
+            idle: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// An idle connection to `addr` that is still open, and whether it was
+    /// one, or a new connection.
+    pub(crate) async fn get(
+        &self,
+        addr: &str,
+        timeout: Option<Duration>,
+    ) -> Result<(Connection, bool), Error> {
+        loop {
+            let conn = match self.idle.lock().unwrap().get_mut(addr) {
+                Some(idle) => idle.pop(),
+                None => None,
+            };
+            match conn {
+                Some(conn) if self.is_fresh(&conn) && conn.is_open() => return Ok((conn, true)),
+                Some(_) => continue,
+                None => break,
+            }
+        }
+
+        Ok((Pool::connect(addr, timeout).await?, false))
+    }
+
+    fn is_fresh(&self, conn: &Connection) -> bool {
+        self.idle_timeout
+            .is_none_or(|timeout| conn.idle_since.elapsed() < timeout)
+    }
+
+    pub(crate) async fn connect(
+        addr: &str,
+        timeout: Option<Duration>,
+    ) -> Result<Connection, Error> {
+        let stream = with_timeout(timeout, TcpStream::connect(addr)).await?;
+        stream.set_nodelay(true)?;
+
+        Ok(Connection {
+            stream,
+            buffer: BytesMut::with_capacity(READ_SIZE),
+            idle_since: Instant::now(),
+        })
+    }
+
+    /// Hands back a connection that finished its last response cleanly.
+    pub(crate) fn put(&self, addr: &str, mut conn: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        idle.retain(|_, conns| {
+            conns.retain(|conn| self.is_fresh(conn));
+            !conns.is_empty()
+        });
+
+        let conns = idle.entry(addr.to_string()).or_default();
+        if conns.len() < self.max_idle {
+            conn.idle_since = Instant::now();
+            conns.push(conn);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use crate::headers::Headers;
+use crate::request::{Body, Framing, RequestMethod};
+use crate::uri::{self, TargetForm, Uri};
+
+mod conn;
+mod response;
+
+pub use response::Response;
+
+use conn::{Connection, Pool};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+const DEFAULT_MAX_IDLE: usize = 16;
+
+// Where to connect for an authority, e.g. `example.com` or `[::1]:8080`.
+fn socket_addr(authority: &str) -> String {
+    match authority.rsplit_once(':') {
+        Some((_, port)) if !port.contains(']') => authority.to_string(),
+        _ => format!("{}:80", authority),
+    }
+}
+
+// Methods that mean something by an empty body, which is said with
+// `Content-Length: 0`.
+fn expects_body(method: &RequestMethod) -> bool {
+    matches!(
+        method,
+        RequestMethod::Post | RequestMethod::Put | RequestMethod::Patch
+    )
+}
+
+// Methods that can be sent twice to the same effect as once.
+fn is_idempotent(method: &RequestMethod) -> bool {
+    matches!(
+        method,
+        RequestMethod::Get
+            | RequestMethod::Head
+            | RequestMethod::Put
+            | RequestMethod::Delete
+            | RequestMethod::Options
+            | RequestMethod::Trace
+    )
+}
+
+// Sends the body with the framing announced in the head.
+async fn send_body(
+    conn: &mut Connection,
+    body: &mut Body,
+    chunked: bool,
+    timeout: Option<Duration>,
+) -> Result<(), Error> {
+    while let Some(data) = body.chunk().await? {
+        if chunked {
+            conn.write_all(format!("{:x}\r\n", data.len()).as_bytes(), timeout)
+                .await?;
+            conn.write_all(&data, timeout).await?;
+            conn.write_all(b"\r\n", timeout).await?;
+        } else {
+            conn.write_all(&data, timeout).await?;
+        }
+    }
+
+    if chunked {
+        let mut last = b"0\r\n".to_vec();
+        body.trailers().serialize(&mut last)?;
+        conn.write_all(&last, timeout).await?;
+    }
+
+    Ok(())
+}
+
+/// An HTTP/1.1 client for `http://` URLs, reading responses with the same
+/// parser the server reads requests with.
+///
+/// Connections are kept open once a response has been read to the end and
+/// reused for later requests to the same host and port. Clones share them.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use httpfromtcp::Client;
+///
+/// let client = Client::new();
+/// let mut response = client.get("http://127.0.0.1:8080/coffee").send().await?;
+/// let body = response.collect().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Client {
+    timeout: Option<Duration>,
+    pool: Pool,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client {
+            timeout: Some(DEFAULT_TIMEOUT),
+            pool: Pool::new(DEFAULT_MAX_IDLE, Some(DEFAULT_IDLE_TIMEOUT)),
+        }
+    }
+
+    /// How long to wait on connecting, and on each read from or write to the
+    /// server.
+    /// Running out fails with [`ErrorKind::TimedOut`].
+    pub fn timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// How long an unused connection is kept open.
+    pub fn idle_timeout(mut self, timeout: Duration) -> Client {
+        self.pool.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// How many unused connections to keep open for each host and port.
+    pub fn max_idle_connections(mut self, max_idle: usize) -> Client {
+        self.pool.max_idle = max_idle;
+        self
+    }
+
+    pub fn request(&self, method: RequestMethod, url: &str) -> RequestBuilder {
+        RequestBuilder {
+            client: self.clone(),
+            method,
+            url: url.to_string(),
+            target: None,
+            headers: Headers::new(),
+            body: Body::default(),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> RequestBuilder {
+        self.request(RequestMethod::Get, url)
+    }
+
+    pub fn head(&self, url: &str) -> RequestBuilder {
+        self.request(RequestMethod::Head, url)
+    }
+
+    pub fn post(&self, url: &str) -> RequestBuilder {
+        self.request(RequestMethod::Post, url)
+    }
+
+    pub fn put(&self, url: &str) -> RequestBuilder {
+        self.request(RequestMethod::Put, url)
+    }
+
+    pub fn delete(&self, url: &str) -> RequestBuilder {
+        self.request(RequestMethod::Delete, url)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+/// A request to send with a [`Client`].
+///
+/// `Host` is filled in from the URL unless set, and the framing is picked
+/// from the body: `Content-Length` when its length is known, `chunked`
+/// otherwise.
+pub struct RequestBuilder {
+    client: Client,
+    method: RequestMethod,
+    url: String,
+    // Sent in place of the URL's path and query, e.g. `*`.
+    target: Option<String>,
+    headers: Headers,
+    body: Body,
+}
+
+impl RequestBuilder {
+    pub fn header(mut self, key: &str, value: impl AsRef<[u8]>) -> RequestBuilder {
+        self.headers.append(key, value);
+        self
+    }
+
+    /// Adds every field of `headers`, keeping those already set.
+    pub fn headers(mut self, headers: &Headers) -> RequestBuilder {
+        for (key, value) in headers.iter() {
+            self.headers.append(key, value);
+        }
+        self
+    }
+
+    /// A streaming [`Body`], e.g. one taken from a request the server is
+    /// handling, is sent on as it is read.
+    pub fn body(mut self, body: impl Into<Body>) -> RequestBuilder {
+        self.body = body.into();
+        self
+    }
+
+    pub(crate) fn target(mut self, target: &str) -> RequestBuilder {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Sends the request and reads the response up to the end of its header
+    /// section. Interim responses are skipped.
+    pub async fn send(self) -> Result<Response, Error> {
+        let RequestBuilder {
+            client,
+            method,
+            url,
+            target,
+            mut headers,
+            mut body,
+        } = self;
+
+        let uri = Uri::parse(&url)?;
+        let authority = match (uri.form, uri.scheme.as_deref(), uri.authority) {
+            (TargetForm::Absolute, Some("http"), Some(authority)) => authority,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "Only http:// URLs are supported",
+                ));
+            }
+        };
+        let addr = socket_addr(&authority);
+        let target = target.unwrap_or_else(|| uri::origin_form(&url));
+
+        if !headers.contains("host") {
+            headers.insert("Host", &authority);
+        }
+        // The framing has to match how the body is sent, so it isn't left to
+        // the caller.
+        headers.remove("content-length");
+        headers.remove("transfer-encoding");
+        let length = body.remaining();
+        match length {
+            Some(0) if !expects_body(&method) => {}
+            Some(n) => headers.append("Content-Length", n.to_string()),
+            None => headers.append("Transfer-Encoding", "chunked"),
+        }
+        let chunked = length.is_none();
+        let close = conn::asks_close(&headers);
+
+        let mut head = format!("{} {} HTTP/1.1\r\n", method.as_str(), target).into_bytes();
+        headers.serialize(&mut head)?;
+
+        // A pooled connection may have been closed by the server just as it
+        // was picked. The request is sent again on a new one only if that is
+        // all that happened: the connection was reset or closed before any of
+        // the response arrived, there's no body to replay, and sending it twice
+        // can't do any harm. A timeout says nothing about whether the server
+        // acted on it, so it never counts.
+        let retry = length == Some(0) && is_idempotent(&method);
+        let mut fresh = false;
+        let (mut conn, mut response) = loop {
+            let (mut conn, reused) = if fresh {
+                (Pool::connect(&addr, client.timeout).await?, false)
+            } else {
+                client.pool.get(&addr, client.timeout).await?
+            };
+
+            let sent = match conn.write_all(&head, client.timeout).await {
+                Ok(()) => send_body(&mut conn, &mut body, chunked, client.timeout).await,
+                Err(e) => Err(e),
+            };
+            let response = match sent {
+                Ok(()) => conn.read_head(client.timeout).await,
+                Err(e) if reused && retry && conn::is_reset(&e) => Ok(None),
+                Err(e) => Err(e),
+            };
+            match response {
+                Ok(Some(response)) => break (conn, response),
+                Ok(None) if reused && retry => fresh = true,
+                Ok(None) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "Server closed the connection",
+                    ));
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        while response.status_code.is_informational() {
+            if response.status_code.code() == 101 {
+                return Err(Error::new(ErrorKind::InvalidData, "Unexpected Upgrade"));
+            }
+            response = match conn.read_head(client.timeout).await? {
+                Some(response) => response,
+                None => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            };
+        }
+
+        let framing = response.framing(method == RequestMethod::Head)?;
+        let decoder = response.decoder(framing);
+        let reusable = !close && response.keep_alive() && !matches!(framing, Framing::Close);
+        let pool = reusable.then(|| (client.pool.clone(), addr));
+
+        Ok(Response::new(
+            response.version,
+            response.status_code,
+            response.headers,
+            decoder,
+            conn,
+            pool,
+            client.timeout,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,107 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+use bytes::Bytes;
+
+use super::conn::{Connection, Pool};
+use crate::headers::Headers;
+use crate::request::{Decoder, HttpVersion};
+use crate::response::StatusCode;
+
+/// A response read by the [`Client`](super::Client). The body is left on the
+/// connection until it is read, and the connection goes back to the client's
+/// pool once it has all been read.
+pub struct Response {
+    pub version: HttpVersion,
+    pub status_code: StatusCode,
+    pub headers: Headers,
+    body_length: Option<u64>,
+    decoder: Decoder,
+    // Gone once the body is done, or failed.
+    conn: Option<Connection>,
+    // Where the connection goes back to, when it can be used again.
+    pool: Option<(Pool, String)>,
+    timeout: Option<Duration>,
+}
+
+impl Response {
+    pub(crate) fn new(
+        version: HttpVersion,
+        status_code: StatusCode,
+        headers: Headers,
+        decoder: Decoder,
+        conn: Connection,
+        pool: Option<(Pool, String)>,
+        timeout: Option<Duration>,
+    ) -> Response {
+        let mut response = Response {
+            version,
+            status_code,
+            headers,
+            body_length: decoder.remaining().map(|n| n as u64),
+            decoder,
+            conn: Some(conn),
+            pool,
+            timeout,
+        };
+        // Nothing to read, the connection can go back right away.
+        if response.decoder.is_done() {
+            response.release();
+        }
+
+        response
+    }
+
+    /// The length of the body when the response gave it up front. `Some(0)`
+    /// for responses that can't have one, e.g. to `HEAD`.
+    pub fn body_length(&self) -> Option<u64> {
+        self.body_length
+    }
+
+    /// The next piece of the body, or `None` once it has all been read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        if self.decoder.is_done() {
+            return Ok(None);
+        }
+        let conn = match self.conn.as_mut() {
+            Some(conn) => conn,
+            None => return Err(Error::from(ErrorKind::BrokenPipe)),
+        };
+
+        match conn.read_body(&mut self.decoder, self.timeout).await {
+            Ok(data) => {
+                if self.decoder.is_done() {
+                    self.release();
+                }
+                Ok(data)
+            }
+            Err(e) => {
+                self.conn = None;
+                Err(e)
+            }
+        }
+    }
+
+    /// Reads the rest of the body into memory.
+    pub async fn collect(&mut self) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+        while let Some(data) = self.chunk().await? {
+            body.extend_from_slice(&data);
+        }
+
+        Ok(body)
+    }
+
+    /// The trailer fields of a chunked body. Empty until the body has been
+    /// read to the end.
+    pub fn trailers(&self) -> &Headers {
+        self.decoder.trailers()
+    }
+
+    fn release(&mut self) {
+        let conn = self.conn.take();
+        if let (Some(conn), Some((pool, addr))) = (conn, self.pool.take()) {
+            pool.put(&addr, conn);
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use super::*;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::request::HttpVersion;
+use crate::response::StatusCode;
+use crate::server::Server;
+use crate::test_util::{echo, start};
+
+fn url(server: &Server, path: &str) -> String {
+    format!("http://{}{}", server.local_addrs()[0], path)
+}
+
+// Answers each connection with `response` once the request head is in.
+async fn canned(response: &'static [u8]) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                read_head(&mut stream).await;
+                stream.write_all(response).await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+// Answers the first request on each connection, then reads a second one and
+// closes the connection on it, or goes quiet if `close` is false. Counts the
+// requests it read.
+async fn drops_second_request(close: bool) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let seen = Arc::new(AtomicUsize::new(0));
+    let count = Arc::clone(&seen);
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let count = Arc::clone(&count);
+            tokio::spawn(async move {
+                read_head(&mut stream).await;
+                count.fetch_add(1, Ordering::SeqCst);
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .await
+                    .unwrap();
+                if read_head(&mut stream).await.is_empty() {
+                    return;
+                }
+                count.fetch_add(1, Ordering::SeqCst);
+                if !close {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            });
+        }
+    });
+    (addr, seen)
+}
+
+async fn read_head(stream: &mut TcpStream) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut byte = [0u8; 1];
+    while !buf.ends_with(b"\r\n\r\n") {
+        if stream.read(&mut byte).await.unwrap() == 0 {
+            break;
+        }
+        buf.push(byte[0]);
+    }
+    buf
+}
+
+// The port the request came from, as echoed back. Reads the rest so the
+// connection can go back to the pool.
+async fn port(response: &mut Response) -> String {
+    let port = response.headers.get("x-peer-port").unwrap().to_string();
+    response.collect().await.unwrap();
+    port
+}
+
+#[tokio::test]
+async fn sized_request_and_response() {
+    let server = start(echo).await;
+    let client = Client::new();
+
+    let mut response = client
+        .post(&url(&server, "/coffee?size=large"))
+        .header("X-Order", "1")
+        .body("hello")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status_code, StatusCode::Ok);
+    assert_eq!(response.version, HttpVersion::Http11);
+    let body = String::from_utf8(response.collect().await.unwrap()).unwrap();
+    assert_eq!(response.body_length(), Some(body.len() as u64));
+
+    let host = format!("Host: {}\n", server.local_addrs()[0]);
+    assert!(body.contains("POST /coffee?size=large\n"));
+    assert!(body.contains(&host));
+    assert!(body.contains("X-Order: 1\n"));
+    assert!(body.contains("Content-Length: 5\n"));
+    assert!(body.ends_with("\n\nhello\n"));
+}
+
+#[tokio::test]
+async fn framing_is_picked_by_the_client() {
+    let server = start(echo).await;
+    let client = Client::new();
+
+    // A GET without a body says nothing about one.
+    let mut response = client.get(&url(&server, "/")).send().await.unwrap();
+    let body = String::from_utf8(response.collect().await.unwrap()).unwrap();
+    assert!(!body.contains("Content-Length"));
+
+    // A POST says its empty body is empty, whatever the caller claimed.
+    let mut response = client
+        .post(&url(&server, "/"))
+        .header("Content-Length", "10")
+        .send()
+        .await
+        .unwrap();
+    let body = String::from_utf8(response.collect().await.unwrap()).unwrap();
+    assert!(body.contains("Content-Length: 0\n"));
+    assert!(!body.contains("Content-Length: 10"));
+}
+
+#[tokio::test]
+async fn chunked_response_with_trailers() {
+    let server = start(echo).await;
+    let client = Client::new();
+
+    let mut response = client.get(&url(&server, "/chunked")).send().await.unwrap();
+    assert_eq!(response.body_length(), None);
+    assert!(response.trailers().is_empty());
+    let body = String::from_utf8(response.collect().await.unwrap()).unwrap();
+    assert!(body.contains("GET /chunked\n"));
+    assert_eq!(response.trailers().get("x-checksum"), Some("abc123"));
+}
+
+#[tokio::test]
+async fn body_delimited_by_close() {
+    let addr = canned(b"HTTP/1.0 200 OK\r\nX-Old: yes\r\n\r\nuntil the end").await;
+    let client = Client::new();
+
+    let mut response = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.version, HttpVersion::Http10);
+    assert_eq!(response.headers.get("x-old"), Some("yes"));
+    assert_eq!(response.body_length(), None);
+    assert_eq!(response.collect().await.unwrap(), b"until the end");
+}
+
+#[tokio::test]
+async fn interim_responses_are_skipped() {
+    let addr = canned(
+        b"HTTP/1.1 100 Continue\r\n\r\n\
+          HTTP/1.1 103 Early Hints\r\nLink: </site.css>\r\n\r\n\
+          HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok",
+    )
+    .await;
+    let client = Client::new();
+
+    let mut response = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status_code, StatusCode::Created);
+    assert!(!response.headers.contains("link"));
+    assert_eq!(response.collect().await.unwrap(), b"ok");
+}
+
+#[tokio::test]
+async fn malformed_responses_fail() {
+    let addr = canned(b"HTTP/1.1 OK\r\n\r\n").await;
+    let client = Client::new();
+    let e = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+    let addr =
+        canned(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n").await;
+    let e = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn connections_are_reused() {
+    let server = start(echo).await;
+    let client = Client::new();
+
+    let mut ports = Vec::new();
+    for path in ["/", "/chunked", "/"] {
+        let mut response = client.get(&url(&server, path)).send().await.unwrap();
+        ports.push(port(&mut response).await);
+    }
+    assert_eq!(ports[0], ports[1]);
+    assert_eq!(ports[1], ports[2]);
+
+    // Not with a response that was left unread, or asked to close.
+    let response = client.get(&url(&server, "/")).send().await.unwrap();
+    drop(response);
+    let mut response = client
+        .get(&url(&server, "/"))
+        .header("Connection", "close")
+        .send()
+        .await
+        .unwrap();
+    let closed = port(&mut response).await;
+    assert_ne!(closed, ports[0]);
+    let mut response = client.get(&url(&server, "/")).send().await.unwrap();
+    assert_ne!(port(&mut response).await, closed);
+}
+
+#[tokio::test]
+async fn head_responses_have_no_body() {
+    let server = start(echo).await;
+    let client = Client::new();
+
+    let response = client.head(&url(&server, "/")).send().await.unwrap();
+    assert!(response.headers.contains("content-length"));
+    assert_eq!(response.body_length(), Some(0));
+    drop(response);
+
+    // Nothing was left unread, so the connection went back already.
+    let mut first = client.get(&url(&server, "/")).send().await.unwrap();
+    let first = port(&mut first).await;
+    let mut second = client.get(&url(&server, "/")).send().await.unwrap();
+    assert_eq!(port(&mut second).await, first);
+}
+
+#[tokio::test]
+async fn idle_connections_expire() {
+    let server = start(echo).await;
+    let client = Client::new().idle_timeout(Duration::from_millis(50));
+
+    let mut response = client.get(&url(&server, "/")).send().await.unwrap();
+    let first = port(&mut response).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut response = client.get(&url(&server, "/")).send().await.unwrap();
+    assert_ne!(port(&mut response).await, first);
+}
+
+#[tokio::test]
+async fn stale_connections_are_replaced() {
+    // Keeps every connection open until its response is sent, then closes
+    // it without saying so.
+    let addr = canned(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok").await;
+    let client = Client::new();
+
+    for _ in 0..3 {
+        let mut response = client
+            .get(&format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.collect().await.unwrap(), b"ok");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn slow_servers_time_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (_stream, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    });
+    let client = Client::new().timeout(Duration::from_millis(100));
+
+    let e = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn idempotent_requests_are_sent_again() {
+    let (addr, seen) = drops_second_request(true).await;
+    let client = Client::new();
+
+    for _ in 0..2 {
+        let mut response = client
+            .get(&format!("http://{}/", addr))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.collect().await.unwrap(), b"ok");
+    }
+    assert_eq!(seen.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn other_requests_are_sent_once() {
+    let (addr, seen) = drops_second_request(true).await;
+    let client = Client::new();
+
+    let mut response = client
+        .post(&format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.collect().await.unwrap(), b"ok");
+
+    let e = client
+        .post(&format!("http://{}/", addr))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(seen.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn timeouts_are_not_retried() {
+    let (addr, seen) = drops_second_request(false).await;
+    let client = Client::new().timeout(Duration::from_millis(100));
+
+    let mut response = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.collect().await.unwrap(), b"ok");
+
+    let e = client
+        .get(&format!("http://{}/", addr))
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(seen.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn stalled_uploads_time_out() {
+    // Takes the connection but never reads from it, so the request body
+    // fills the socket buffers.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (_stream, _) = listener.accept().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    });
+    let client = Client::new().timeout(Duration::from_millis(100));
+
+    let e = client
+        .post(&format!("http://{}/", addr))
+        .body(vec![b'x'; 64 * 1024 * 1024])
+        .send()
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn only_http_urls() {
+    let client = Client::new();
+    for url in ["https://example.com/", "/coffee", "example.com:80"] {
+        let e = client.get(url).send().await.err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+    assert_eq!(socket_addr("example.com"), "example.com:80");
+    assert_eq!(socket_addr("example.com:8080"), "example.com:8080");
+    assert_eq!(socket_addr("[::1]"), "[::1]:80");
+    assert_eq!(socket_addr("[::1]:8080"), "[::1]:8080");
+}
@@ -0,0 +1,104 @@
+// HTTP-dates (RFC 9110 section 5.6.7), as seconds since the Unix epoch.
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Howard Hinnant's days_from_civil / civil_from_days.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// e.g. : Sun, 06 Nov 1994 08:49:37 GMT
+pub(crate) fn format_http_date(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday.
+    let weekday = DAYS[(days + 4).rem_euclid(7) as usize];
+
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        weekday,
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+fn parse_number(digits: &str, len: usize) -> Option<i64> {
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn parse_time(time: &str) -> Option<i64> {
+    let mut parts = time.split(':');
+    let hours = parse_number(parts.next()?, 2)?;
+    let minutes = parse_number(parts.next()?, 2)?;
+    let seconds = parse_number(parts.next()?, 2)?;
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+fn to_secs(year: i64, month: &str, day: i64, time: &str) -> Option<u64> {
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    if !(1..=31).contains(&day) {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * 86400 + parse_time(time)?;
+    u64::try_from(secs).ok()
+}
+
+/// Accepts the IMF-fixdate format along with the obsolete RFC 850 and
+/// asctime ones, which recipients still have to understand.
+pub(crate) fn parse_http_date(value: &str) -> Option<u64> {
+    let parts = value.split_whitespace().collect::<Vec<&str>>();
+    match parts.as_slice() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        [_, day, month, year, time, "GMT"] => {
+            to_secs(parse_number(year, 4)?, month, parse_number(day, 2)?, time)
+        }
+        // Sunday, 06-Nov-94 08:49:37 GMT
+        [_, date, time, "GMT"] => {
+            let mut date = date.split('-');
+            let day = parse_number(date.next()?, 2)?;
+            let month = date.next()?;
+            let year = parse_number(date.next()?, 2)?;
+            let year = if year < 70 { 2000 + year } else { 1900 + year };
+            to_secs(year, month, day, time)
+        }
+        // Sun Nov  6 08:49:37 1994
+        [_, month, day, time, year] => {
+            let day = parse_number(day, 1).or_else(|| parse_number(day, 2))?;
+            to_secs(parse_number(year, 4)?, month, day, time)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,378 @@
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::UNIX_EPOCH;
+
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::request::{Request, RequestMethod};
+use crate::response::{StatusCode, reject};
+use crate::server::{Handler, HandlerError, Writer};
+
+mod date;
+
+use date::{format_http_date, parse_http_date};
+
+// e.g. : site.css
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => "application/octet-stream",
+    }
+}
+
+// What a `Range` header asks for, once checked against the file length.
+#[derive(Debug, PartialEq)]
+enum Range {
+    // No range, or one that has to be ignored.
+    Full,
+    Partial { start: u64, end: u64 },
+    Unsatisfiable,
+}
+
+// Only a single byte range is served, anything else gets the whole file.
+// e.g. : bytes=0-499, bytes=500-, bytes=-500
+fn parse_range(value: &str, len: u64) -> Range {
+    let Some((unit, ranges)) = value.split_once('=') else {
+        return Range::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Range::Full;
+    }
+    let ranges = ranges
+        .split(',')
+        .map(|r| r.trim())
+        .filter(|r| !r.is_empty())
+        .collect::<Vec<&str>>();
+    let [range] = ranges.as_slice() else {
+        return Range::Full;
+    };
+    let Some((first, last)) = range.split_once('-') else {
+        return Range::Full;
+    };
+
+    let parse = |digits: &str| {
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse::<u64>().ok()
+    };
+
+    if first.is_empty() {
+        return match parse(last) {
+            Some(0) => Range::Unsatisfiable,
+            Some(_) if len == 0 => Range::Unsatisfiable,
+            Some(suffix) => Range::Partial {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            },
+            None => Range::Full,
+        };
+    }
+
+    let Some(start) = parse(first) else {
+        return Range::Full;
+    };
+    let end = match last {
+        "" => u64::MAX,
+        last => match parse(last) {
+            Some(end) if end >= start => end,
+            _ => return Range::Full,
+        },
+    };
+    if start >= len {
+        return Range::Unsatisfiable;
+    }
+
+    Range::Partial {
+        start,
+        end: end.min(len - 1),
+    }
+}
+
+// Weak comparison, for `If-None-Match`.
+fn etag_matches(value: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    value.trim() == "*" || value.split(',').any(|tag| opaque(tag) == opaque(etag))
+}
+
+fn not_modified(req: &Request, etag: &str, modified: u64) -> bool {
+    if let Some(value) = req.headers.get_joined("if-none-match") {
+        return etag_matches(&value, etag);
+    }
+    match req.headers.get_joined("if-modified-since") {
+        Some(value) => parse_http_date(&value).is_some_and(|since| modified <= since),
+        None => false,
+    }
+}
+
+// A range only applies to the representation the client already has part
+// of, which takes a strong match.
+fn range_applies(req: &Request, etag: &str, modified: u64) -> bool {
+    match req.headers.get_joined("if-range") {
+        Some(value) if value.trim().starts_with('"') => value.trim() == etag,
+        Some(value) => parse_http_date(&value) == Some(modified),
+        None => true,
+    }
+}
+
+// pchar from RFC 3986, less the `%` that starts an escape.
+fn percent_encode_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&b) {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+// Where a directory asked for without its trailing `/` is redirected to.
+// Rebuilt from the decoded segments rather than echoed, so empty ones can't
+// turn it into a protocol-relative `//host/` redirect.
+fn directory_location(req: &Request) -> String {
+    let mut location = String::from("/");
+    for segment in req.request_line.uri.segments.iter() {
+        if !segment.is_empty() {
+            location.push_str(&percent_encode_segment(segment));
+            location.push('/');
+        }
+    }
+    if let Some((_, query)) = req.request_line.request_target.split_once('?') {
+        location.push('?');
+        location.push_str(query);
+    }
+    location
+}
+
+/// Serves the files under a directory, mapping the path after `prefix` to a
+/// path under `root`. Directories are answered with their `index.html`.
+///
+/// Files are streamed with their length up front. `ETag` and `Last-Modified`
+/// are sent with every file, so clients can revalidate with
+/// `If-None-Match` or `If-Modified-Since`, and a single `Range` is answered
+/// with `206 Partial Content`.
+///
+/// Paths with `.` or `..` segments, or with a separator percent-encoded into
+/// a segment, are refused, as is anything that resolves outside of `root`
+/// through a symlink.
+pub struct StaticFiles {
+    prefix: Vec<String>,
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    pub fn new(prefix: &str, root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            prefix: prefix
+                .split('/')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string())
+                .collect(),
+            root: root.into(),
+        }
+    }
+
+    // The path under the root the request is for, and whether it asked for a
+    // directory.
+    fn relative_path(&self, segments: &[String]) -> Result<(PathBuf, bool), StatusCode> {
+        let rest = match segments.strip_prefix(self.prefix.as_slice()) {
+            Some(rest) => rest,
+            None => return Err(StatusCode::NotFound),
+        };
+
+        let mut path = PathBuf::new();
+        for segment in rest.iter().filter(|s| !s.is_empty()) {
+            if segment == "." || segment == ".." || segment.contains(['/', '\\', '\0']) {
+                return Err(StatusCode::Forbidden);
+            }
+            path.push(segment);
+        }
+
+        let directory = rest.last().is_some_and(|s| s.is_empty());
+        Ok((path, directory))
+    }
+}
+
+// Resolves symlinks and makes sure the result is still under the root.
+async fn resolve(root: &Path, path: &Path) -> Result<PathBuf, StatusCode> {
+    let root = tokio::fs::canonicalize(root).await.map_err(status_for)?;
+    let path = tokio::fs::canonicalize(path).await.map_err(status_for)?;
+    if !path.starts_with(&root) {
+        return Err(StatusCode::Forbidden);
+    }
+    Ok(path)
+}
+
+fn status_for(error: std::io::Error) -> StatusCode {
+    match error.kind() {
+        ErrorKind::NotFound | ErrorKind::NotADirectory => StatusCode::NotFound,
+        ErrorKind::PermissionDenied => StatusCode::Forbidden,
+        _ => StatusCode::InternalServerError,
+    }
+}
+
+async fn serve_file(
+    mut writer: Writer,
+    req: Request,
+    root: PathBuf,
+    relative: PathBuf,
+    directory: bool,
+) -> Option<HandlerError> {
+    let mut path = match resolve(&root, &root.join(relative)).await {
+        Ok(path) => path,
+        Err(status_code) => return reject(writer, status_code).await,
+    };
+    let is_dir = match tokio::fs::metadata(&path).await {
+        Ok(metadata) => metadata.is_dir(),
+        Err(e) => return reject(writer, status_for(e)).await,
+    };
+    if is_dir {
+        if !directory {
+            // Built from percent-encoded segments and the query the parser
+            // already checked, so it's a valid field value.
+            let location = directory_location(&req);
+            writer.set_header("Location", &location).unwrap();
+            return reject(writer, StatusCode::MovedPermanently).await;
+        }
+        path = match resolve(&root, &path.join("index.html")).await {
+            Ok(path) => path,
+            Err(status_code) => return reject(writer, status_code).await,
+        };
+    } else if directory {
+        return reject(writer, StatusCode::NotFound).await;
+    }
+
+    let mut file = match File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => return reject(writer, status_for(e)).await,
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        Ok(_) => return reject(writer, StatusCode::NotFound).await,
+        Err(e) => return reject(writer, status_for(e)).await,
+    };
+
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    let etag = format!("\"{:x}-{:x}\"", modified, len);
+
+    writer.set_header("ETag", &etag).unwrap();
+    writer
+        .set_header("Last-Modified", format_http_date(modified))
+        .unwrap();
+    if not_modified(&req, &etag, modified) {
+        writer.set_status(StatusCode::NotModified);
+        return None;
+    }
+
+    writer.set_header("Accept-Ranges", "bytes").unwrap();
+    let range = match req.headers.get_joined("range") {
+        Some(value) if range_applies(&req, &etag, modified) => parse_range(&value, len),
+        _ => Range::Full,
+    };
+    let (start, end) = match range {
+        Range::Full => (0, len),
+        Range::Partial { start, end } => {
+            writer.set_status(StatusCode::PartialContent);
+            writer
+                .set_header("Content-Range", format!("bytes {}-{}/{}", start, end, len))
+                .unwrap();
+            (start, end + 1)
+        }
+        Range::Unsatisfiable => {
+            writer
+                .set_header("Content-Range", format!("bytes */{}", len))
+                .unwrap();
+            return reject(writer, StatusCode::RangeNotSatisfiable).await;
+        }
+    };
+
+    writer
+        .set_header("Content-Type", content_type(&path))
+        .unwrap();
+    let sent = async {
+        file.seek(SeekFrom::Start(start)).await?;
+        writer.start_with_length(end - start).await?;
+        if writer.is_head() {
+            return Ok(0);
+        }
+        tokio::io::copy(&mut file.take(end - start), &mut writer).await
+    };
+    // A file that shrank while being sent leaves the response short, which
+    // the server turns into a closed connection. The path stays in the log,
+    // it's nothing the client should see.
+    match sent.await {
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Failed to send {}: {}", path.display(), e);
+            Some(HandlerError {
+                status_code: StatusCode::InternalServerError,
+                message: "Internal Server Error\n".to_string(),
+            })
+        }
+    }
+}
+
+impl Handler for StaticFiles {
+    fn call(
+        &self,
+        mut writer: Writer,
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        if !matches!(
+            req.request_line.method,
+            RequestMethod::Get | RequestMethod::Head
+        ) {
+            writer.set_header("Allow", "GET, HEAD").unwrap();
+            return Box::pin(reject(writer, StatusCode::MethodNotAllowed));
+        }
+
+        match self.relative_path(&req.request_line.uri.segments) {
+            Ok((relative, directory)) => Box::pin(serve_file(
+                writer,
+                req,
+                self.root.clone(),
+                relative,
+                directory,
+            )),
+            Err(status_code) => Box::pin(reject(writer, status_code)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,388 @@
+use super::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::request::{HttpVersion, request_from_reader};
+use crate::response::ResponseWriter;
+use crate::test_util::call;
+
+use super::date::{format_http_date, parse_http_date};
+
+const HELLO: &str = "Hello, world!\n";
+
+// A scratch directory with a site under `root` and a file outside of it.
+struct Site {
+    dir: PathBuf,
+}
+
+impl Site {
+    fn new() -> Site {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "httpfromtcp-files-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("docs")).unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+        std::fs::write(root.join("hello.txt"), HELLO).unwrap();
+        std::fs::write(root.join("site.css"), "body {}").unwrap();
+        std::fs::write(root.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("escape.txt")).unwrap();
+            std::os::unix::fs::symlink(root.join("hello.txt"), root.join("link.txt")).unwrap();
+        }
+        Site { dir }
+    }
+
+    fn handler(&self) -> StaticFiles {
+        StaticFiles::new("/static", self.dir.join("root"))
+    }
+}
+
+impl Drop for Site {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+async fn get(handler: &StaticFiles, target: &str, headers: &str) -> String {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n",
+        target, headers
+    );
+    call(handler, request.as_bytes()).await
+}
+
+fn header<'a>(output: &'a str, name: &str) -> Option<&'a str> {
+    let head = output.split("\r\n\r\n").next().unwrap();
+    head.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(": "))
+}
+
+#[tokio::test]
+async fn serves_files_with_their_type() {
+    let site = Site::new();
+
+    let output = get(&site.handler(), "/static/hello.txt", "").await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(
+        header(&output, "Content-Type"),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(header(&output, "Content-Length"), Some("14"));
+    assert_eq!(header(&output, "Accept-Ranges"), Some("bytes"));
+    assert!(header(&output, "ETag").is_some());
+    assert!(header(&output, "Last-Modified").is_some());
+    assert!(output.ends_with(&format!("\r\n\r\n{}", HELLO)));
+
+    let output = get(&site.handler(), "/static/site.css", "").await;
+    assert_eq!(
+        header(&output, "Content-Type"),
+        Some("text/css; charset=utf-8")
+    );
+    assert!(output.ends_with("\r\n\r\nbody {}"));
+}
+
+#[tokio::test]
+async fn head_has_length_but_no_body() {
+    let site = Site::new();
+
+    let output = call(
+        &site.handler(),
+        b"HEAD /static/hello.txt HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(header(&output, "Content-Length"), Some("14"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn large_files_are_streamed() {
+    let site = Site::new();
+    let data = (0..=255).cycle().take(1024 * 1024).collect::<Vec<u8>>();
+    std::fs::write(site.dir.join("root/large.bin"), &data).unwrap();
+
+    let request = b"GET /static/large.bin HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let mut request = request_from_reader(&request[..]).await.unwrap();
+    request.request_line.method = RequestMethod::Get;
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let reading = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        buf
+    });
+    let (writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+    assert!(site.handler().call(writer, request).await.is_none());
+    assert!(response.is_streaming());
+    drop(response.finish().await.unwrap());
+
+    let output = reading.await.unwrap();
+    let head_end = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&output[..head_end]);
+    assert_eq!(
+        header(&head, "Content-Type"),
+        Some("application/octet-stream")
+    );
+    assert_eq!(header(&head, "Content-Length"), Some("1048576"));
+    assert!(output[head_end..] == data[..]);
+}
+
+#[tokio::test]
+async fn directories_serve_their_index() {
+    let site = Site::new();
+
+    let output = get(&site.handler(), "/static/docs/", "").await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert_eq!(
+        header(&output, "Content-Type"),
+        Some("text/html; charset=utf-8")
+    );
+    assert!(output.ends_with("\r\n\r\n<h1>Docs</h1>"));
+
+    let output = get(&site.handler(), "/static/docs?lang=en", "").await;
+    assert!(output.starts_with("HTTP/1.1 301 Moved Permanently\r\n"));
+    assert_eq!(header(&output, "Location"), Some("/static/docs/?lang=en"));
+
+    let output = get(&site.handler(), "/static", "").await;
+    assert_eq!(header(&output, "Location"), Some("/static/"));
+
+    // Never a protocol-relative `//docs/`, and decoded segments go back
+    // out escaped.
+    let root = StaticFiles::new("/", site.dir.join("root"));
+    let output = get(&root, "//docs", "").await;
+    assert_eq!(header(&output, "Location"), Some("/docs/"));
+    std::fs::create_dir_all(site.dir.join("root/a b")).unwrap();
+    let output = get(&root, "/a%20b", "").await;
+    assert_eq!(header(&output, "Location"), Some("/a%20b/"));
+
+    let output = get(&site.handler(), "/static/empty/", "").await;
+    assert!(output.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let output = get(&site.handler(), "/static/hello.txt/", "").await;
+    assert!(output.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[tokio::test]
+async fn missing_files_are_not_found() {
+    let site = Site::new();
+
+    for target in [
+        "/static/missing.txt",
+        "/static/docs/missing/",
+        "/other/hello.txt",
+    ] {
+        let output = get(&site.handler(), target, "").await;
+        assert!(
+            output.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            target
+        );
+    }
+}
+
+#[tokio::test]
+async fn traversal_is_refused() {
+    let site = Site::new();
+
+    for target in [
+        "/static/../secret.txt",
+        "/static/docs/../../secret.txt",
+        "/static/%2e%2e/secret.txt",
+        "/static/.%2E/secret.txt",
+        "/static/..%2Fsecret.txt",
+        "/static/docs%2F..%2F..%2Fsecret.txt",
+        "/static/..%5Csecret.txt",
+        "/static/./hello.txt",
+    ] {
+        let output = get(&site.handler(), target, "").await;
+        assert!(
+            output.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+            "{}",
+            target
+        );
+        assert!(!output.ends_with("secret"));
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks_stay_inside_the_root() {
+    let site = Site::new();
+
+    let output = get(&site.handler(), "/static/escape.txt", "").await;
+    assert!(output.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+
+    let output = get(&site.handler(), "/static/link.txt", "").await;
+    assert!(output.ends_with(&format!("\r\n\r\n{}", HELLO)));
+}
+
+#[tokio::test]
+async fn only_get_and_head_are_allowed() {
+    let site = Site::new();
+
+    let output = call(
+        &site.handler(),
+        b"POST /static/hello.txt HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert_eq!(header(&output, "Allow"), Some("GET, HEAD"));
+}
+
+#[tokio::test]
+async fn revalidation() {
+    let site = Site::new();
+    let output = get(&site.handler(), "/static/hello.txt", "").await;
+    let etag = header(&output, "ETag").unwrap().to_string();
+    let last_modified = header(&output, "Last-Modified").unwrap().to_string();
+
+    for headers in [
+        format!("If-None-Match: {}\r\n", etag),
+        format!("If-None-Match: \"other\", W/{}\r\n", etag),
+        "If-None-Match: *\r\n".to_string(),
+        format!("If-Modified-Since: {}\r\n", last_modified),
+    ] {
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(
+            output.starts_with("HTTP/1.1 304 Not Modified\r\n"),
+            "{}",
+            headers
+        );
+        assert_eq!(header(&output, "ETag"), Some(etag.as_str()));
+        assert!(header(&output, "Content-Length").is_none());
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+
+    for headers in [
+        "If-None-Match: \"other\"\r\n".to_string(),
+        "If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n".to_string(),
+        "If-Modified-Since: yesterday\r\n".to_string(),
+        // If-None-Match wins over If-Modified-Since.
+        format!(
+            "If-None-Match: \"other\"\r\nIf-Modified-Since: {}\r\n",
+            last_modified
+        ),
+    ] {
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{}", headers);
+        assert!(output.ends_with(&format!("\r\n\r\n{}", HELLO)));
+    }
+}
+
+#[tokio::test]
+async fn range_requests() {
+    let site = Site::new();
+
+    let cases = [
+        ("bytes=0-4", "bytes 0-4/14", "Hello"),
+        ("bytes=7-", "bytes 7-13/14", "world!\n"),
+        ("bytes=-7", "bytes 7-13/14", "world!\n"),
+        ("bytes=7-100", "bytes 7-13/14", "world!\n"),
+        ("bytes=-100", "bytes 0-13/14", HELLO),
+    ];
+    for (range, content_range, body) in cases {
+        let headers = format!("Range: {}\r\n", range);
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(
+            output.starts_with("HTTP/1.1 206 Partial Content\r\n"),
+            "{}",
+            range
+        );
+        assert_eq!(header(&output, "Content-Range"), Some(content_range));
+        assert_eq!(
+            header(&output, "Content-Length"),
+            Some(body.len().to_string().as_str())
+        );
+        assert!(output.ends_with(&format!("\r\n\r\n{}", body)));
+    }
+
+    for range in ["bytes=14-", "bytes=-0"] {
+        let headers = format!("Range: {}\r\n", range);
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(output.starts_with("HTTP/1.1 416 Range Not Satisfiable\r\n"));
+        assert_eq!(header(&output, "Content-Range"), Some("bytes */14"));
+    }
+
+    // Multiple, malformed or foreign ranges are ignored.
+    for range in ["bytes=0-1, 3-4", "bytes=4-2", "bytes=a-", "lines=0-4"] {
+        let headers = format!("Range: {}\r\n", range);
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{}", range);
+        assert!(output.ends_with(&format!("\r\n\r\n{}", HELLO)));
+    }
+}
+
+#[tokio::test]
+async fn if_range_needs_a_current_validator() {
+    let site = Site::new();
+    let output = get(&site.handler(), "/static/hello.txt", "").await;
+    let etag = header(&output, "ETag").unwrap().to_string();
+    let last_modified = header(&output, "Last-Modified").unwrap().to_string();
+
+    for if_range in [etag.clone(), last_modified] {
+        let headers = format!("Range: bytes=0-4\r\nIf-Range: {}\r\n", if_range);
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(output.starts_with("HTTP/1.1 206 Partial Content\r\n"));
+    }
+
+    for if_range in [
+        "\"other\"".to_string(),
+        format!("W/{}", etag),
+        "Thu, 01 Jan 1970 00:00:00 GMT".to_string(),
+    ] {
+        let headers = format!("Range: bytes=0-4\r\nIf-Range: {}\r\n", if_range);
+        let output = get(&site.handler(), "/static/hello.txt", &headers).await;
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"), "{}", if_range);
+        assert!(output.ends_with(&format!("\r\n\r\n{}", HELLO)));
+    }
+}
+
+#[test]
+fn range_parsing() {
+    assert_eq!(
+        parse_range("bytes=0-0", 10),
+        Range::Partial { start: 0, end: 0 }
+    );
+    assert_eq!(
+        parse_range("Bytes = 2-3 ", 10),
+        Range::Partial { start: 2, end: 3 }
+    );
+    assert_eq!(parse_range("bytes=-5", 0), Range::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-", 0), Range::Unsatisfiable);
+    assert_eq!(parse_range("bytes=99999999999999999999-", 10), Range::Full);
+    assert_eq!(parse_range("bytes=+1-2", 10), Range::Full);
+    assert_eq!(parse_range("bytes=", 10), Range::Full);
+    assert_eq!(parse_range("bytes", 10), Range::Full);
+}
+
+#[test]
+fn http_dates() {
+    assert_eq!(format_http_date(0), "Thu, 01 Jan 1970 00:00:00 GMT");
+    assert_eq!(format_http_date(784111777), "Sun, 06 Nov 1994 08:49:37 GMT");
+    assert_eq!(format_http_date(951782400), "Tue, 29 Feb 2000 00:00:00 GMT");
+
+    for date in [
+        "Sun, 06 Nov 1994 08:49:37 GMT",
+        "Sunday, 06-Nov-94 08:49:37 GMT",
+        "Sun Nov  6 08:49:37 1994",
+    ] {
+        assert_eq!(parse_http_date(date), Some(784111777), "{}", date);
+    }
+
+    for date in [
+        "",
+        "Sun, 06 Nov 1994 08:49:37 UTC",
+        "Sun, 6 Nov 1994 08:49:37 GMT",
+        "Sun, 06 Foo 1994 08:49:37 GMT",
+        "Sun, 06 Nov 1994 24:00:00 GMT",
+        "Wed, 31 Dec 1969 23:59:59 GMT",
+    ] {
+        assert_eq!(parse_http_date(date), None, "{}", date);
+    }
+}
@@ -1,92 +1,274 @@
-use std::{collections::HashMap, io::Error};
+use bytes::{Buf, Bytes, BytesMut};
 
-fn is_token(field_name: &str) -> bool {
-    if field_name
-        .chars()
-        .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
-    {
-        return true;
+use crate::request::ParseError;
+
+pub(crate) fn is_tchar(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+pub(crate) fn is_token(token: &[u8]) -> bool {
+    !token.is_empty() && token.iter().all(|b| is_tchar(*b))
+}
+
+// field-value = *( VCHAR / obs-text / SP / HTAB ), obs-text being any byte
+// from 0x80 up.
+fn is_field_value(value: &[u8]) -> bool {
+    value
+        .iter()
+        .all(|b| *b == b' ' || *b == b'\t' || (0x21..=0x7e).contains(b) || *b >= 0x80)
+}
+
+// Fields built by a handler or a client go on the wire as they are, so they
+// are held to what the parser would accept. A CR or LF in one would start a
+// field of its own.
+pub(crate) fn check_field(key: &str, value: &[u8]) -> Result<(), std::io::Error> {
+    if !is_token(key.as_bytes()) || !is_field_value(value) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Invalid Header Field",
+        ));
+    }
+    Ok(())
+}
+
+fn is_ows(b: &u8) -> bool {
+    *b == b' ' || *b == b'\t'
+}
+
+pub(crate) fn trim_ows(value: &[u8]) -> &[u8] {
+    let start = value.iter().position(|b| !is_ows(b)).unwrap_or(value.len());
+    let end = value
+        .iter()
+        .rposition(|b| !is_ows(b))
+        .map_or(start, |i| i + 1);
+    &value[start..end]
+}
+
+/// Where the first CRLF in `buffer` starts.
+pub(crate) fn find_crlf(buffer: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while let Some(i) = buffer[start..].iter().position(|b| *b == b'\n') {
+        let lf = start + i;
+        if lf > 0 && buffer[lf - 1] == b'\r' {
+            return Some(lf - 1);
+        }
+        start = lf + 1;
     }
 
-    false
+    None
 }
 
-fn parse_header(header_line: &str) -> Result<(String, String), Error> {
-    let error_malformed_header = Error::new(std::io::ErrorKind::InvalidData, "Malformed Header");
+#[derive(Clone, Debug)]
+struct Field {
+    name: Bytes,
+    value: Bytes,
+}
 
-    let separator_index = match header_line.find(':') {
-        Some(i) => i,
-        None => return Err(error_malformed_header),
-    };
+impl Field {
+    // Names are either tokens or came in as a `&str`, so always UTF-8.
+    fn name(&self) -> &str {
+        std::str::from_utf8(&self.name).unwrap_or_default()
+    }
 
-    let field_name = &header_line[..separator_index];
-    let field_value = header_line[(separator_index + 1)..].trim();
+    fn is(&self, key: &str) -> bool {
+        self.name.eq_ignore_ascii_case(key.as_bytes())
+    }
+}
+
+// e.g. : Host: localhost:42069
+// `line` is a whole field line without its CRLF. The name and value end up
+// as slices of it. A line starting with whitespace is either obs-fold or
+// whitespace before the first field, and both are refused rather than
+// guessing what an intermediary made of them. So is whitespace before the
+// colon, since the name has to be a token.
+fn parse_header(line: Bytes) -> Option<Field> {
+    let separator_index = line.iter().position(|b| *b == b':')?;
 
+    let field_name = &line[..separator_index];
     if !is_token(field_name) {
-        return Err(error_malformed_header);
+        return None;
     }
 
-    // TODO: Use bytes everywhere rather than String
-    if separator_index > 0 && header_line.as_bytes()[separator_index - 1] == b' ' {
-        return Err(error_malformed_header);
+    let value = trim_ows(&line[separator_index + 1..]);
+    if !is_field_value(value) {
+        return None;
     }
 
-    Ok((field_name.to_string(), field_value.to_string()))
+    Some(Field {
+        name: line.slice_ref(field_name),
+        value: line.slice_ref(value),
+    })
 }
 
+/// Header fields in the order they were added, each name with the casing it
+/// was sent with. Names are matched case-insensitively, and a name can
+/// appear more than once.
+///
+/// Values are kept as bytes, since a client may send obs-text that isn't
+/// UTF-8. The `&str` getters skip such values, the `_bytes` ones don't.
+#[derive(Clone, Debug)]
 pub struct Headers {
-    pub headers: HashMap<String, String>,
+    fields: Vec<Field>,
 }
 
 impl Headers {
     pub fn new() -> Headers {
-        Headers {
-            headers: HashMap::new(),
+        Headers { fields: Vec::new() }
+    }
+
+    /// The first value for `key`, if it is valid UTF-8.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.get_bytes(key)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.fields
+            .iter()
+            .find(|field| field.is(key))
+            .map(|field| &field.value[..])
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.get_all_bytes(key)
+            .into_iter()
+            .filter_map(|value| std::str::from_utf8(value).ok())
+            .collect()
+    }
+
+    pub fn get_all_bytes(&self, key: &str) -> Vec<&[u8]> {
+        self.fields
+            .iter()
+            .filter(|field| field.is(key))
+            .map(|field| &field.value[..])
+            .collect()
+    }
+
+    /// Every value for `key` joined with commas, which is how list-based
+    /// fields like `Connection` combine. Never use it on `Set-Cookie`.
+    pub fn get_joined(&self, key: &str) -> Option<String> {
+        let values = self.get_all_bytes(key);
+        if values.is_empty() {
+            return None;
         }
+
+        Some(String::from_utf8_lossy(&values.join(&b", "[..])).into_owned())
     }
-    pub fn get(&mut self, key: &str) -> Option<&String> {
-        self.headers.get(&key.to_lowercase())
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.fields.iter().any(|field| field.is(key))
     }
 
-    pub fn set(&mut self, key: &str, value: &str) {
-        if let Some(existing_value) = self.get(key.to_lowercase().as_str()) {
-            let new_value = format!("{}, {}", existing_value, value);
-            self.headers.insert(key.to_lowercase(), new_value);
-        } else {
-            self.headers.insert(key.to_lowercase(), value.to_string());
+    /// Adds a field, keeping any others with the same name.
+    pub fn append(&mut self, key: &str, value: impl AsRef<[u8]>) {
+        self.fields.push(Field {
+            name: Bytes::copy_from_slice(key.as_bytes()),
+            value: Bytes::copy_from_slice(value.as_ref()),
+        });
+    }
+
+    /// Sets a field, replacing every other with the same name. It takes the
+    /// place of the first one it replaces.
+    pub fn insert(&mut self, key: &str, value: impl AsRef<[u8]>) {
+        match self.fields.iter().position(|field| field.is(key)) {
+            Some(i) => {
+                self.fields[i] = Field {
+                    name: Bytes::copy_from_slice(key.as_bytes()),
+                    value: Bytes::copy_from_slice(value.as_ref()),
+                };
+                let mut j = i + 1;
+                while j < self.fields.len() {
+                    if self.fields[j].is(key) {
+                        self.fields.remove(j);
+                    } else {
+                        j += 1;
+                    }
+                }
+            }
+            None => self.append(key, value),
         }
     }
 
-    pub fn parse(&mut self, buffer: &[u8]) -> Result<(bool, usize), Error> {
-        let error_malformed_header =
-            Error::new(std::io::ErrorKind::InvalidData, "Malformed Header");
+    /// Removes every field named `key`, returning the first value.
+    pub fn remove(&mut self, key: &str) -> Option<Bytes> {
+        let mut removed = None;
+        self.fields.retain(|field| {
+            if !field.is(key) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(field.value.clone());
+            }
+            false
+        });
+
+        removed
+    }
 
-        let request_string = match std::str::from_utf8(buffer) {
-            Ok(s) => s,
-            Err(_) => return Err(error_malformed_header),
-        };
+    // Field lines and the blank line after them, as a header section or
+    // trailers go on the wire. Nothing is written if a field is invalid.
+    pub(crate) fn serialize(&self, buf: &mut Vec<u8>) -> Result<(), std::io::Error> {
+        for (key, value) in self.iter() {
+            check_field(key, value)?;
+        }
+        for (key, value) in self.iter() {
+            buf.extend_from_slice(key.as_bytes());
+            buf.extend_from_slice(b": ");
+            buf.extend_from_slice(value);
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf.extend_from_slice(b"\r\n");
+        Ok(())
+    }
 
-        let mut rest = request_string;
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.fields
+            .iter()
+            .map(|field| (field.name(), &field.value[..]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Parses the complete field lines at the start of `buffer`, returning
+    /// whether the blank line ending the section was reached and how many
+    /// bytes were used. Offsets in errors are relative to `buffer`.
+    pub fn parse(&mut self, buffer: &[u8]) -> Result<(bool, usize), ParseError> {
+        let mut buffer = BytesMut::from(buffer);
+        let len = buffer.len();
+        let done = self.parse_buf(&mut buffer)?;
+
+        Ok((done, len - buffer.len()))
+    }
+
+    // Like `parse`, but takes the lines off the front of `buffer` without
+    // copying them.
+    pub(crate) fn parse_buf(&mut self, buffer: &mut BytesMut) -> Result<bool, ParseError> {
+        let mut read = 0;
         loop {
-            let index = match rest.find("\r\n") {
+            let index = match find_crlf(buffer) {
                 Some(i) => i,
-                None => return Ok((false, (request_string.len() - rest.len()))),
+                None => return Ok(false),
             };
 
             if index == 0 {
-                return Ok((true, (request_string.len() - rest.len() + 2)));
+                buffer.advance(2);
+                return Ok(true);
             }
 
-            let header_line = rest[..index].trim().to_string();
-
-            let (field_name, field_value) = match parse_header(&header_line) {
-                Ok((s, t)) => (s, t),
-                Err(e) => return Err(e),
-            };
-
-            self.set(&field_name, &field_value);
+            let mut line = buffer.split_to(index + 2).freeze();
+            line.truncate(index);
+            match parse_header(line) {
+                Some(field) => self.fields.push(field),
+                None => return Err(ParseError::MalformedHeader { offset: read }),
+            }
 
-            rest = &rest[index + 2..];
+            read += index + 2;
         }
     }
 }
@@ -8,7 +8,7 @@ fn valid_single_header() {
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
     assert_eq!(n, 25);
     assert!(done);
 }
@@ -26,16 +26,31 @@ fn invalid_spacing_header() {
 #[test]
 fn valid_single_header_with_spaces() {
     let mut headers = Headers::new();
-    let data = b"     Host:    localhost:42069    \r\n\r\n";
+    let data = b"Host:    localhost:42069 \t  \r\n\r\n";
 
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
-    assert_eq!(n, 37);
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
+    assert_eq!(n, 32);
     assert!(done);
 }
 
+#[test]
+fn leading_whitespace_and_obs_fold() {
+    for data in [
+        &b"     Host: localhost:42069\r\n\r\n"[..],
+        &b"X-Long: first\r\n  second\r\n\r\n"[..],
+        &b"X-Long: first\r\n\tsecond\r\n\r\n"[..],
+    ] {
+        let mut headers = Headers::new();
+        assert!(matches!(
+            headers.parse(data),
+            Err(ParseError::MalformedHeader { .. })
+        ));
+    }
+}
+
 #[test]
 fn valid_two_headers() {
     let mut headers = Headers::new();
@@ -44,8 +59,8 @@ fn valid_two_headers() {
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
-    assert_eq!(headers.get("User-Agent"), Some(&"TestAgent".to_string()));
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
+    assert_eq!(headers.get("User-Agent"), Some("TestAgent"));
     assert_eq!(n, 48);
     assert!(done);
 }
@@ -53,15 +68,15 @@ fn valid_two_headers() {
 #[test]
 fn valid_two_header_with_existing_headers() {
     let mut headers = Headers::new();
-    headers.set("existing", "Header");
+    headers.append("existing", "Header");
     let data = b"Host: localhost:42069\r\nUser-Agent: TestAgent\r\n\r\n";
 
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("existing"), Some(&"Header".to_string()));
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
-    assert_eq!(headers.get("User-Agent"), Some(&"TestAgent".to_string()));
+    assert_eq!(headers.get("existing"), Some("Header"));
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
+    assert_eq!(headers.get("User-Agent"), Some("TestAgent"));
     assert_eq!(n, 48);
     assert!(done);
 }
@@ -74,8 +89,8 @@ fn capital_header_names() {
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
-    assert_eq!(headers.get("User-Agent"), Some(&"TestAgent".to_string()));
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
+    assert_eq!(headers.get("User-Agent"), Some("TestAgent"));
     assert_eq!(n, 48);
     assert!(done);
 }
@@ -88,7 +103,12 @@ fn multiple_values_for_single_header() {
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Cookie"), Some(&"value1, value2".to_string()));
+    assert_eq!(headers.get("Cookie"), Some("value1"));
+    assert_eq!(headers.get_all("Cookie"), vec!["value1", "value2"]);
+    assert_eq!(
+        headers.get_joined("Cookie"),
+        Some("value1, value2".to_string())
+    );
     assert_eq!(n, 34);
     assert!(done);
 }
@@ -101,8 +121,107 @@ fn missing_ending_crlf() {
     let result = headers.parse(data);
     let (done, n) = result.unwrap();
 
-    assert_eq!(headers.get("Host"), Some(&"localhost:42069".to_string()));
-    assert_eq!(headers.get("User-Agent"), Some(&"TestAgent".to_string()));
+    assert_eq!(headers.get("Host"), Some("localhost:42069"));
+    assert_eq!(headers.get("User-Agent"), Some("TestAgent"));
     assert_eq!(n, 46);
     assert!(!done);
 }
+
+#[test]
+fn keeps_order_and_casing() {
+    let mut headers = Headers::new();
+    let data = b"Host: localhost:42069\r\nX-Trace-ID: abc\r\naccept: */*\r\n\r\n";
+
+    headers.parse(data).unwrap();
+
+    let fields = headers.iter().collect::<Vec<(&str, &[u8])>>();
+    assert_eq!(
+        fields,
+        vec![
+            ("Host", &b"localhost:42069"[..]),
+            ("X-Trace-ID", &b"abc"[..]),
+            ("accept", &b"*/*"[..])
+        ]
+    );
+    assert_eq!(headers.get("x-trace-id"), Some("abc"));
+    assert_eq!(headers.len(), 3);
+}
+
+#[test]
+fn append_insert_and_remove() {
+    let mut headers = Headers::new();
+    headers.append("Set-Cookie", "a=1");
+    headers.append("Content-Type", "text/plain");
+    headers.append("set-cookie", "b=2");
+
+    assert!(headers.contains("SET-COOKIE"));
+    assert_eq!(headers.get_all("Set-Cookie"), vec!["a=1", "b=2"]);
+
+    headers.insert("Set-Cookie", "c=3");
+    let fields = headers.iter().collect::<Vec<(&str, &[u8])>>();
+    assert_eq!(
+        fields,
+        vec![
+            ("Set-Cookie", &b"c=3"[..]),
+            ("Content-Type", &b"text/plain"[..])
+        ]
+    );
+
+    assert_eq!(
+        headers.remove("content-type"),
+        Some(Bytes::from_static(b"text/plain"))
+    );
+    assert_eq!(headers.remove("content-type"), None);
+    assert!(!headers.contains("Content-Type"));
+    assert_eq!(headers.len(), 1);
+}
+
+#[test]
+fn obs_text_in_values() {
+    let mut headers = Headers::new();
+    let data = b"X-Name: caf\xe9\r\nX-Plain: yes\r\n\r\n";
+
+    let (done, n) = headers.parse(data).unwrap();
+
+    assert!(done);
+    assert_eq!(n, data.len());
+    assert_eq!(headers.get_bytes("X-Name"), Some(&b"caf\xe9"[..]));
+    assert_eq!(headers.get("X-Name"), None);
+    assert_eq!(headers.get("X-Plain"), Some("yes"));
+}
+
+#[test]
+fn control_characters_in_values() {
+    for data in [
+        &b"X-Name: a\x00b\r\n\r\n"[..],
+        &b"X-Name: a\rb\r\n\r\n"[..],
+        &b"X-Name: a\nb\r\n\r\n"[..],
+    ] {
+        let mut headers = Headers::new();
+        assert!(headers.parse(data).is_err());
+    }
+}
+
+#[test]
+fn serialize_refuses_invalid_fields() {
+    let mut headers = Headers::new();
+    headers.append("Host", "localhost");
+    headers.append("X-Name", b"caf\xe9");
+    let mut buf = Vec::new();
+    headers.serialize(&mut buf).unwrap();
+    assert_eq!(buf, b"Host: localhost\r\nX-Name: caf\xe9\r\n\r\n");
+
+    for (key, value) in [
+        ("Location", &b"/x\r\nSet-Cookie: evil=1"[..]),
+        ("X-Name", &b"a\x00b"[..]),
+        ("Bad Name", &b"1"[..]),
+        ("Bad:Name", &b"1"[..]),
+    ] {
+        let mut headers = Headers::new();
+        headers.append("Host", "localhost");
+        headers.append(key, value);
+        let mut buf = Vec::new();
+        assert!(headers.serialize(&mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+}
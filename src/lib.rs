@@ -1,8 +1,22 @@
+pub mod client;
+pub mod files;
 pub mod headers;
+pub mod middleware;
+pub mod proxy;
 pub mod request;
 pub mod response;
+pub mod router;
 pub mod server;
+pub mod uri;
 
-pub use request::Request;
+#[cfg(test)]
+mod test_util;
+
+pub use client::Client;
+pub use files::StaticFiles;
+pub use middleware::{Layers, Middleware, Next};
+pub use proxy::Proxy;
+pub use request::{Body, Limits, ParseError, Request, Timeouts};
 pub use response::StatusCode;
-pub use server::{Handler, HandlerError, Server, Writer, serve};
+pub use router::Router;
+pub use server::{Handler, HandlerError, Server, ServerBuilder, Writer, serve};
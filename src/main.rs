@@ -1,38 +1,36 @@
 use tokio::io::AsyncWriteExt;
 
-use crate::request::Request;
-use crate::server::Writer;
-
-mod headers;
-mod request;
-mod response;
-mod server;
+use httpfromtcp::{HandlerError, Request, Router, StatusCode, Writer, serve};
 
 #[tokio::main]
 async fn main() {
     let port = 42069;
 
-    let server = server::serve(port, |mut stream: Writer, request: Request| async move {
-        if request.request_line.request_target == "/yourproblem" {
-            return Some(server::HandlerError {
-                status_code: response::StatusCode::InternalServerError,
+    let router = Router::new()
+        .get("/yourproblem", |_stream: Writer, _request: Request| async {
+            Some(HandlerError {
+                status_code: StatusCode::InternalServerError,
                 message: "Your problem is too complex.".to_string(),
-            });
-        } else if request.request_line.request_target == "/myproblem" {
-            return Some(server::HandlerError {
-                status_code: response::StatusCode::InternalServerError,
+            })
+        })
+        .get("/myproblem", |_stream: Writer, _request: Request| async {
+            Some(HandlerError {
+                status_code: StatusCode::InternalServerError,
                 message: "Woopsie, my bad!\n".to_string(),
-            });
-        }
+            })
+        })
+        .get(
+            "/*path",
+            |mut stream: Writer, _request: Request| async move {
+                stream.write_all(b"All Good! frfr\n").await.unwrap();
+                None
+            },
+        );
 
-        stream.write_all(b"All Good! frfr\n").await.unwrap();
-        None
-    })
-    .await
-    .expect("Cannot start server");
+    let server = serve(port, router).await.expect("Cannot start server");
 
     tokio::signal::ctrl_c().await.unwrap();
     println!("Shutting down…");
 
-    server.close();
+    server.shutdown().await;
 }
@@ -0,0 +1,152 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::request::Request;
+use crate::server::{Handler, HandlerError, Writer};
+
+/// Wraps a [`Handler`], running before and after it.
+///
+/// The request can be changed before it is passed on with [`Next::run`], and
+/// the response after, through the same writer: its status, headers and, if
+/// the response hasn't started, its body. A middleware that doesn't call
+/// `next` answers the request itself.
+pub trait Middleware: Send + Sync + 'static {
+    fn call(
+        &self,
+        writer: Writer,
+        req: Request,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>>;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(Writer, Request, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<HandlerError>> + Send + 'static,
+{
+    fn call(
+        &self,
+        writer: Writer,
+        req: Request,
+        next: Next,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        Box::pin((self)(writer, req, next))
+    }
+}
+
+/// The rest of the stack after a middleware: the layers inside it, then the
+/// handler.
+#[derive(Clone)]
+pub struct Next {
+    layers: Arc<[Box<dyn Middleware>]>,
+    // The layer to run next, the handler once past the last one.
+    index: usize,
+    handler: Arc<dyn Handler>,
+}
+
+impl Next {
+    /// Passes the request on. Whatever it writes goes to `writer`, which
+    /// stays usable once it returns.
+    ///
+    /// A [`HandlerError`] from inside is written to `writer` as the response,
+    /// with the headers set before the call, so it can be seen and changed
+    /// like any other. Only one that comes after the response started, and
+    /// can't be written any more, is returned.
+    pub fn run(
+        self,
+        writer: &Writer,
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        let mut writer = writer.share();
+        let headers = writer.headers();
+        let layers = Arc::clone(&self.layers);
+        let inner = match layers.get(self.index) {
+            Some(layer) => {
+                let next = Next {
+                    index: self.index + 1,
+                    ..self
+                };
+                layer.call(writer.share(), req, next)
+            }
+            None => self.handler.call(writer.share(), req),
+        };
+
+        Box::pin(async move {
+            match inner.await {
+                Some(err) if !writer.is_started() => {
+                    writer.fail(err.status_code, &err.message, headers);
+                    None
+                }
+                err => err,
+            }
+        })
+    }
+}
+
+/// Stacks middleware around a handler. Layers run in the order they were
+/// added on the way in, and in reverse on the way out.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use httpfromtcp::{Layers, Next, Request, Writer, serve};
+///
+/// let handler = Layers::new()
+///     .layer(|writer: Writer, req: Request, next: Next| async move {
+///         let target = req.request_line.request_target.clone();
+///         let err = next.run(&writer, req).await;
+///         println!("{} {}", writer.status().code(), target);
+///         err
+///     })
+///     .wrap(|_w: Writer, _r: Request| async { None });
+/// let server = serve(8080, handler).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Layers {
+    layers: Vec<Box<dyn Middleware>>,
+}
+
+impl Layers {
+    pub fn new() -> Layers {
+        Layers { layers: Vec::new() }
+    }
+
+    pub fn layer<M: Middleware>(mut self, middleware: M) -> Layers {
+        self.layers.push(Box::new(middleware));
+        self
+    }
+
+    pub fn wrap<H: Handler>(self, handler: H) -> Layered {
+        Layered {
+            next: Next {
+                layers: self.layers.into(),
+                index: 0,
+                handler: Arc::new(handler),
+            },
+        }
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A handler with its middleware, built by [`Layers::wrap`].
+pub struct Layered {
+    next: Next,
+}
+
+impl Handler for Layered {
+    fn call(
+        &self,
+        writer: Writer,
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        self.next.clone().run(&writer, req)
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,181 @@
+use super::*;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::response::StatusCode;
+use crate::test_util::{hello, send, start};
+
+// Marks where the handler ran among the layers.
+async fn traced_hello(mut writer: Writer, req: Request) -> Option<HandlerError> {
+    writer.append_header("X-Order", "handler").unwrap();
+    hello(writer, req).await
+}
+
+const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+// Marks the way in and the way out with its name.
+fn trace(name: &'static str) -> impl Middleware {
+    move |writer: Writer, req: Request, next: Next| async move {
+        let mut writer = writer;
+        writer
+            .append_header("X-Order", format!("{} in", name))
+            .unwrap();
+        let err = next.run(&writer, req).await;
+        writer
+            .append_header("X-Order", format!("{} out", name))
+            .unwrap();
+        err
+    }
+}
+
+#[tokio::test]
+async fn layers_run_in_order() {
+    let handler = Layers::new()
+        .layer(trace("outer"))
+        .layer(trace("inner"))
+        .wrap(traced_hello);
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    let order = output
+        .lines()
+        .filter_map(|line| line.strip_prefix("X-Order: "))
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        order,
+        ["outer in", "inner in", "handler", "inner out", "outer out"]
+    );
+    assert!(output.ends_with("Hello from /"));
+}
+
+#[tokio::test]
+async fn requests_can_be_changed_on_the_way_in() {
+    let handler = Layers::new()
+        .layer(|writer: Writer, mut req: Request, next: Next| async move {
+            req.request_line.uri.path = "/rewritten".to_string();
+            next.run(&writer, req).await
+        })
+        .wrap(hello);
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    assert!(output.ends_with("Hello from /rewritten"));
+}
+
+#[tokio::test]
+async fn responses_can_be_changed_on_the_way_out() {
+    let handler = Layers::new()
+        .layer(|writer: Writer, req: Request, next: Next| async move {
+            let mut writer = writer;
+            let err = next.run(&writer, req).await;
+
+            assert_eq!(writer.status(), StatusCode::Ok);
+            assert!(writer.headers().contains("x-order"));
+            writer.set_status(StatusCode::Created);
+            writer.remove_header("X-Order");
+            writer.set_header("X-Shouted", "yes").unwrap();
+            let body = writer.take_body().unwrap();
+            writer.write_all(&body.to_ascii_uppercase()).await.unwrap();
+            err
+        })
+        .wrap(traced_hello);
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    assert!(output.starts_with("HTTP/1.1 201 Created\r\n"));
+    assert!(!output.contains("X-Order"));
+    assert!(output.contains("X-Shouted: yes\r\n"));
+    assert!(output.contains("Content-Length: 12\r\n"));
+    assert!(output.ends_with("\r\n\r\nHELLO FROM /"));
+}
+
+#[tokio::test]
+async fn layers_can_answer_themselves() {
+    let called = Arc::new(AtomicBool::new(false));
+    let seen = Arc::clone(&called);
+    let handler = Layers::new()
+        .layer(|mut writer: Writer, req: Request, next: Next| async move {
+            if !req.headers.contains("authorization") {
+                writer.set_status(StatusCode::Unauthorized);
+                writer.set_header("WWW-Authenticate", "Basic").unwrap();
+                return None;
+            }
+            next.run(&writer, req).await
+        })
+        .wrap(move |writer: Writer, req: Request| {
+            seen.store(true, Ordering::SeqCst);
+            hello(writer, req)
+        });
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    assert!(output.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+    assert!(output.contains("WWW-Authenticate: Basic\r\n"));
+    assert!(!called.load(Ordering::SeqCst));
+
+    let output = send(
+        &server,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Basic YTpi\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(called.load(Ordering::SeqCst));
+}
+
+#[tokio::test]
+async fn streamed_bodies_are_already_out() {
+    let handler = Layers::new()
+        .layer(|writer: Writer, req: Request, next: Next| async move {
+            let mut writer = writer;
+            let err = next.run(&writer, req).await;
+            assert!(writer.is_started());
+            assert_eq!(writer.take_body(), None);
+            // Too late for the head, which went out with the first chunk.
+            writer.set_header("X-Late", "yes").unwrap();
+            err
+        })
+        .wrap(|mut writer: Writer, _req: Request| async move {
+            writer.start_chunked().await.unwrap();
+            writer.write_all(b"streamed").await.unwrap();
+            None
+        });
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(!output.contains("X-Late"));
+    assert!(output.contains("\r\n8\r\nstreamed\r\n0\r\n"));
+}
+
+#[tokio::test]
+async fn handler_errors_are_responses_on_the_way_out() {
+    let handler = Layers::new()
+        .layer(|writer: Writer, req: Request, next: Next| async move {
+            let mut writer = writer;
+            writer.set_header("X-Before", "yes").unwrap();
+            let err = next.run(&writer, req).await;
+
+            assert!(err.is_none());
+            assert_eq!(writer.status(), StatusCode::BadRequest);
+            writer.set_header("X-After", "yes").unwrap();
+            err
+        })
+        .wrap(|mut writer: Writer, _req: Request| async move {
+            writer.set_header("X-Handler", "yes").unwrap();
+            writer.write_all(b"half done").await.unwrap();
+            Some(HandlerError {
+                status_code: StatusCode::BadRequest,
+                message: "Bad Request\n".to_string(),
+            })
+        });
+    let server = start(handler).await;
+
+    let output = send(&server, GET).await;
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.contains("X-Before: yes\r\n"));
+    assert!(output.contains("X-After: yes\r\n"));
+    assert!(!output.contains("X-Handler"));
+    assert!(output.ends_with("\r\n\r\nBad Request\n"));
+}
@@ -0,0 +1,277 @@
+use std::io::{Error, ErrorKind};
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+
+use crate::client::Client;
+use crate::headers::Headers;
+use crate::request::{HttpVersion, Request, RequestMethod};
+use crate::response::StatusCode;
+use crate::server::{Handler, HandlerError, Writer};
+use crate::uri::{self, TargetForm};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_IDLE: usize = 16;
+
+// How the proxy names itself in `Via`.
+const PSEUDONYM: &str = "httpfromtcp";
+
+// Fields that only mean something for a single connection (RFC 9110 section
+// 7.6.1), along with the framing, which gets redone on the other side.
+const HOP_BY_HOP: [&str; 10] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+fn end_to_end(headers: &Headers) -> Headers {
+    let listed = headers.get_joined("connection").unwrap_or_default();
+    let listed = listed
+        .split(',')
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<&str>>();
+
+    let mut forwarded = Headers::new();
+    for (key, value) in headers.iter() {
+        let hop_by_hop = HOP_BY_HOP
+            .iter()
+            .chain(listed.iter())
+            .any(|name| key.eq_ignore_ascii_case(name));
+        if !hop_by_hop {
+            forwarded.append(key, value);
+        }
+    }
+
+    forwarded
+}
+
+// e.g. : 1.1 httpfromtcp
+fn via(version: HttpVersion) -> String {
+    let version = match version {
+        HttpVersion::Http10 => "1.0",
+        HttpVersion::Http11 => "1.1",
+    };
+    format!("{} {}", version, PSEUDONYM)
+}
+
+fn append_list(headers: &mut Headers, key: &str, element: &str) {
+    let value = match headers.get_joined(key) {
+        Some(value) => format!("{}, {}", value, element),
+        None => element.to_string(),
+    };
+    headers.insert(key, value);
+}
+
+// e.g. : for=192.0.2.60;proto=http;host="example.com"
+fn forwarded_element(peer: Option<IpAddr>, host: Option<&str>) -> String {
+    let mut pairs = Vec::new();
+    match peer {
+        Some(IpAddr::V4(ip)) => pairs.push(format!("for={}", ip)),
+        Some(IpAddr::V6(ip)) => pairs.push(format!("for=\"[{}]\"", ip)),
+        None => pairs.push("for=unknown".to_string()),
+    }
+    pairs.push("proto=http".to_string());
+    if let Some(host) = host {
+        let host = host.replace('\\', "\\\\").replace('"', "\\\"");
+        pairs.push(format!("host=\"{}\"", host));
+    }
+
+    pairs.join(";")
+}
+
+// The target in origin-form, as the upstream is no proxy.
+fn upstream_target(req: &Request) -> String {
+    let target = &req.request_line.request_target;
+    if req.request_line.uri.form != TargetForm::Absolute {
+        return target.clone();
+    }
+    uri::origin_form(target)
+}
+
+fn bad_gateway(error: Error) -> Option<HandlerError> {
+    let status_code = match error.kind() {
+        ErrorKind::TimedOut => StatusCode::GatewayTimeout,
+        _ => StatusCode::BadGateway,
+    };
+
+    Some(HandlerError {
+        message: format!("{}\n", status_code.reason()),
+        status_code,
+    })
+}
+
+/// Forwards requests to an upstream server over HTTP/1.1 and streams its
+/// responses back.
+///
+/// Hop-by-hop fields are dropped both ways, `X-Forwarded-For`,
+/// `Forwarded` and `Via` are added to the request and `Via` to the
+/// response. Connections to the upstream are kept open and reused.
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use httpfromtcp::{Proxy, serve};
+///
+/// let server = serve(8080, Proxy::new("127.0.0.1:3000")).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Proxy {
+    upstream: String,
+    client: Client,
+}
+
+impl Proxy {
+    /// `upstream` is a `host:port` to connect to.
+    pub fn new(upstream: &str) -> Proxy {
+        Proxy {
+            upstream: upstream.to_string(),
+            client: Client::new()
+                .timeout(DEFAULT_TIMEOUT)
+                .max_idle_connections(DEFAULT_MAX_IDLE),
+        }
+    }
+
+    /// How long to wait on connecting to the upstream, and on each read
+    /// from it. Running out is answered with `504`.
+    pub fn timeout(mut self, timeout: Duration) -> Proxy {
+        self.client = self.client.timeout(timeout);
+        self
+    }
+
+    /// How many idle connections to the upstream to keep open.
+    pub fn max_idle_connections(mut self, max_idle: usize) -> Proxy {
+        self.client = self.client.max_idle_connections(max_idle);
+        self
+    }
+}
+
+async fn forward(
+    client: Client,
+    upstream: String,
+    mut writer: Writer,
+    mut req: Request,
+) -> Option<HandlerError> {
+    let mut headers = end_to_end(&req.headers);
+    // Whether to go on with the body was settled with the client already.
+    headers.remove("expect");
+    let peer = req.peer_addr.map(|addr| addr.ip());
+    if let Some(ip) = peer {
+        append_list(&mut headers, "X-Forwarded-For", &ip.to_string());
+    }
+    let element = forwarded_element(peer, req.headers.get("host"));
+    append_list(&mut headers, "Forwarded", &element);
+    append_list(&mut headers, "Via", &via(req.request_line.http_version));
+
+    // The body is sent on as it arrives. A body the client fails to send
+    // fails the request upstream too, and is answered by the server.
+    let sent = client
+        .request(
+            req.request_line.method.clone(),
+            &format!("http://{}/", upstream),
+        )
+        .target(&upstream_target(&req))
+        .headers(&headers)
+        .body(std::mem::take(&mut req.body))
+        .send()
+        .await;
+    let mut response = match sent {
+        Ok(response) => response,
+        Err(e) => return bad_gateway(e),
+    };
+
+    writer.set_status(response.status_code.clone());
+    writer.no_default_content_type();
+    let mut headers = end_to_end(&response.headers);
+    append_list(&mut headers, "Via", &via(response.version));
+    for (key, value) in headers.iter() {
+        if let Err(e) = writer.append_header(key, value) {
+            return bad_gateway(e);
+        }
+    }
+
+    // A response to HEAD has no body, but says how long the GET one is.
+    let code = response.status_code.code();
+    let length = match response.body_length() {
+        Some(0) if writer.is_head() && !matches!(code, 204 | 304) => response
+            .headers
+            .get("content-length")
+            .and_then(|n| n.parse::<u64>().ok()),
+        length => length,
+    };
+    let started = match length {
+        Some(0) => Ok(()),
+        Some(n) => writer.start_with_length(n).await,
+        None => writer.start_chunked().await,
+    };
+    if let Err(e) = started {
+        return Some(HandlerError {
+            status_code: StatusCode::BadGateway,
+            message: format!("Failed to start response: {}", e),
+        });
+    }
+
+    loop {
+        let data = match response.chunk().await {
+            Ok(Some(data)) => data,
+            Ok(None) => break,
+            Err(e) => {
+                return Some(HandlerError {
+                    status_code: StatusCode::BadGateway,
+                    message: format!("Upstream failed mid-response: {}", e),
+                });
+            }
+        };
+        if let Err(e) = writer.write_all(&data).await {
+            return Some(HandlerError {
+                status_code: StatusCode::BadGateway,
+                message: format!("Failed to write response: {}", e),
+            });
+        }
+    }
+
+    for (key, value) in end_to_end(response.trailers()).iter() {
+        if let Err(e) = writer.set_trailer(key, value) {
+            return bad_gateway(e);
+        }
+    }
+
+    None
+}
+
+impl Handler for Proxy {
+    fn call(
+        &self,
+        writer: Writer,
+        req: Request,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        // Tunnels aren't something a reverse proxy does.
+        if req.request_line.method == RequestMethod::Connect {
+            return Box::pin(async {
+                Some(HandlerError {
+                    status_code: StatusCode::NotImplemented,
+                    message: "Not Implemented\n".to_string(),
+                })
+            });
+        }
+
+        Box::pin(forward(
+            self.client.clone(),
+            self.upstream.clone(),
+            writer,
+            req,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,375 @@
+use super::*;
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::server::Server;
+use crate::test_util::{echo, read_response, response_body, send, start};
+
+async fn start_proxy(upstream: SocketAddr) -> Arc<Server> {
+    start(Proxy::new(&upstream.to_string())).await
+}
+
+#[tokio::test]
+async fn forwards_requests_with_forwarding_headers() {
+    let upstream = start(echo).await;
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+
+    let output = send(
+        &proxy,
+        b"POST /echo?x=1 HTTP/1.1\r\n\
+          Host: example.com\r\n\
+          Connection: close, X-Hop\r\n\
+          X-Hop: secret\r\n\
+          Keep-Alive: timeout=5\r\n\
+          X-Forwarded-For: 203.0.113.7\r\n\
+          X-Custom: yes\r\n\
+          Content-Length: 5\r\n\
+          \r\n\
+          hello",
+    )
+    .await;
+
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Via: 1.1 httpfromtcp\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    let (_, seen) = output.split_once("\r\n\r\n").unwrap();
+    assert!(seen.starts_with("POST /echo?x=1\n"));
+    assert!(seen.contains("Host: example.com\n"));
+    assert!(seen.contains("X-Custom: yes\n"));
+    assert!(seen.contains("X-Forwarded-For: 203.0.113.7, 127.0.0.1\n"));
+    assert!(seen.contains("Forwarded: for=127.0.0.1;proto=http;host=\"example.com\"\n"));
+    assert!(seen.contains("Via: 1.1 httpfromtcp\n"));
+    assert!(seen.contains("Content-Length: 5\n"));
+    assert!(!seen.contains("X-Hop"));
+    assert!(!seen.contains("Keep-Alive"));
+    assert!(!seen.contains("Connection"));
+    assert!(seen.ends_with("\n\nhello\n"));
+}
+
+#[tokio::test]
+async fn absolute_targets_are_sent_in_origin_form() {
+    let upstream = start(echo).await;
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+
+    let output = send(
+        &proxy,
+        b"GET http://example.com?q=1 HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    let (_, seen) = output.split_once("\r\n\r\n").unwrap();
+    assert!(seen.starts_with("GET /?q=1\n"));
+
+    // An HTTP/1.0 client may leave out Host, the upstream still needs one.
+    let output = send(&proxy, b"GET /old HTTP/1.0\r\n\r\n").await;
+    let (_, seen) = output.split_once("\r\n\r\n").unwrap();
+    assert!(seen.contains(&format!("Host: {}\n", upstream.local_addrs()[0])));
+    assert!(seen.contains("Via: 1.0 httpfromtcp\n"));
+}
+
+#[tokio::test]
+async fn chunked_bodies_both_ways() {
+    let upstream = start(echo).await;
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+
+    let output = send(
+        &proxy,
+        b"POST /chunked HTTP/1.1\r\nHost: localhost\r\n\
+          Connection: close\r\n\
+          Transfer-Encoding: chunked\r\n\
+          \r\n\
+          5\r\nhello\r\n6\r\n world\r\n0\r\nX-Sent: yes\r\n\r\n",
+    )
+    .await;
+
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(output.contains("Via: 1.1 httpfromtcp\r\n"));
+    let body = String::from_utf8(response_body(output.as_bytes())).unwrap();
+    assert!(body.contains("\nhello world\nX-Sent: yes\n"));
+    assert!(body.contains("Transfer-Encoding: chunked\n"));
+    assert!(output.ends_with("\r\n0\r\nX-Checksum: abc123\r\n\r\n"));
+
+    // HTTP/1.0 clients get the same body delimited by the close instead.
+    let output = send(&proxy, b"GET /chunked HTTP/1.0\r\n\r\n").await;
+    assert!(output.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(!output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(output.ends_with("\n\n\n"));
+}
+
+#[tokio::test]
+async fn upstream_connections_are_reused() {
+    let upstream = start(|mut writer: Writer, req: Request| async move {
+        let port = req.peer_addr.unwrap().port().to_string();
+        writer.write_all(port.as_bytes()).await.unwrap();
+        None
+    })
+    .await;
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+    let mut stream = TcpStream::connect(proxy.local_addrs()[0]).await.unwrap();
+
+    let mut ports = Vec::new();
+    for _ in 0..3 {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let output = read_response(&mut stream).await;
+        let (_, port) = output.split_once("\r\n\r\n").unwrap();
+        ports.push(port.to_string());
+    }
+    assert_eq!(ports[0], ports[1]);
+    assert_eq!(ports[1], ports[2]);
+}
+
+#[tokio::test]
+async fn stale_pooled_connections_are_replaced() {
+    let upstream = Server::builder()
+        .bind("127.0.0.1:0")
+        .idle_timeout(Duration::from_millis(50))
+        .serve(echo)
+        .await
+        .expect("Cannot start server");
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+    let mut stream = TcpStream::connect(proxy.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET /one HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream).await.contains("GET /one\n"));
+
+    // The upstream closes the pooled connection in the meantime.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    stream
+        .write_all(b"GET /two HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream).await.contains("GET /two\n"));
+}
+
+#[tokio::test]
+async fn bodiless_responses_pass_through() {
+    let upstream = start(|mut writer: Writer, _req: Request| async move {
+        writer.set_status(StatusCode::NotModified);
+        writer.set_header("ETag", "\"v1\"").unwrap();
+        None
+    })
+    .await;
+    let proxy = start_proxy(upstream.local_addrs()[0]).await;
+    let mut stream = TcpStream::connect(proxy.local_addrs()[0]).await.unwrap();
+
+    for _ in 0..2 {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nIf-None-Match: \"v1\"\r\n\r\n")
+            .await
+            .unwrap();
+        let output = read_response(&mut stream).await;
+        assert!(output.starts_with("HTTP/1.1 304 Not Modified\r\n"));
+        assert!(output.contains("ETag: \"v1\"\r\n"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+}
+
+#[tokio::test]
+async fn unreachable_upstream_is_bad_gateway() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let proxy = start_proxy(addr).await;
+
+    let output = send(
+        &proxy,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+}
+
+#[tokio::test]
+async fn slow_upstream_is_gateway_timeout() {
+    let upstream = start(|mut writer: Writer, _req: Request| async move {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        writer.write_all(b"late").await.unwrap();
+        None
+    })
+    .await;
+    let proxy =
+        Proxy::new(&upstream.local_addrs()[0].to_string()).timeout(Duration::from_millis(100));
+    let proxy = start(proxy).await;
+
+    let output = send(
+        &proxy,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 504 Gateway Timeout\r\n"));
+}
+
+#[tokio::test]
+async fn interim_and_close_delimited_responses() {
+    // Nothing built on `serve` answers like this, so a raw stand-in does.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).await.unwrap();
+            head.push(byte[0]);
+        }
+        stream
+            .write_all(
+                b"HTTP/1.1 103 Early Hints\r\nLink: </style.css>\r\n\r\n\
+                  HTTP/1.1 299 Whatever\r\nContent-Type: text/plain\r\n\r\n\
+                  until the end",
+            )
+            .await
+            .unwrap();
+    });
+    let proxy = start_proxy(addr).await;
+
+    let output = send(
+        &proxy,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 299 Whatever\r\n"));
+    assert!(!output.contains("103"));
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(output.ends_with("\r\n\r\nd\r\nuntil the end\r\n0\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn field_values_are_forwarded_byte_for_byte() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nX-Name: caf\xe9\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  2\r\nok\r\n0\r\nX-Sum: \xff\r\n\r\n",
+            )
+            .await
+            .unwrap();
+    });
+    let proxy = start_proxy(addr).await;
+
+    let mut stream = TcpStream::connect(proxy.local_addrs()[0]).await.unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).await.unwrap();
+    let contains = |needle: &[u8]| output.windows(needle.len()).any(|w| w == needle);
+    assert!(contains(b"\r\nX-Name: caf\xe9\r\n"));
+    assert!(contains(b"\r\n0\r\nX-Sum: \xff\r\n\r\n"));
+    assert!(!contains(b"Content-Type"));
+}
+
+#[tokio::test]
+async fn no_content_type_is_made_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        for response in [
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok"[..],
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"[..],
+        ] {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(response).await.unwrap();
+        }
+    });
+    let proxy = start_proxy(addr).await;
+
+    let request = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+    let output = send(&proxy, request).await;
+    assert!(output.ends_with("\r\n\r\nok"));
+    assert!(!output.contains("Content-Type"));
+    let output = send(&proxy, request).await;
+    assert!(output.contains("Content-Length: 0\r\n"));
+    assert!(!output.contains("Content-Type"));
+}
+
+#[tokio::test]
+async fn head_requests_reach_the_upstream_as_head() {
+    // Answers with a length but no body, which only works for HEAD.
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (seen, received) = tokio::sync::oneshot::channel();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        seen.send(buf[..n].to_vec()).unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n")
+            .await
+            .unwrap();
+    });
+    let proxy = start_proxy(addr).await;
+
+    let output = send(
+        &proxy,
+        b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(received.await.unwrap().starts_with(b"HEAD / HTTP/1.1\r\n"));
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 1000\r\n"));
+    assert!(!output.contains("Content-Type"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn ambiguous_upstream_responses_are_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 1024];
+        let _ = stream.read(&mut buf).await.unwrap();
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+    });
+    let proxy = start_proxy(addr).await;
+
+    let output = send(
+        &proxy,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 502 Bad Gateway\r\n"));
+}
+
+#[test]
+fn end_to_end_fields() {
+    let mut headers = Headers::new();
+    headers.append("Connection", "keep-alive, X-Private");
+    headers.append("X-Private", "1");
+    headers.append("TE", "trailers");
+    headers.append("Content-Length", "5");
+    headers.append("Accept", "*/*");
+
+    let forwarded = end_to_end(&headers);
+    assert_eq!(
+        forwarded.iter().map(|(k, _)| k).collect::<Vec<&str>>(),
+        vec!["Accept"]
+    );
+}
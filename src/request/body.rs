@@ -0,0 +1,534 @@
+use std::cmp::min;
+use std::future::poll_fn;
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+
+use bytes::{Buf, Bytes, BytesMut};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Sleep;
+
+use crate::headers::{Headers, find_crlf, is_tchar, trim_ows};
+use crate::request::{HeaderSize, Limits, ParseError, RequestReader};
+use crate::response::Continue;
+
+pub(crate) type Connection = Box<dyn AsyncRead + Send + Unpin>;
+
+// Chunk sizes are a handful of hex digits, the rest is extensions we skip.
+const MAX_CHUNK_SIZE_LINE: usize = 4096;
+
+fn split_token(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let end = data
+        .iter()
+        .position(|b| !is_tchar(*b))
+        .unwrap_or(data.len());
+    (end > 0).then(|| data.split_at(end))
+}
+
+// quoted-string = DQUOTE *( qdtext / quoted-pair ) DQUOTE, returns what
+// follows it.
+fn skip_quoted_string(data: &[u8]) -> Option<&[u8]> {
+    let is_qdtext = |b: u8| b == b'\t' || (b' '..=b'~').contains(&b) || b >= 0x80;
+    let mut i = 1;
+    loop {
+        match *data.get(i)? {
+            b'"' => return Some(&data[i + 1..]),
+            b'\\' if data.get(i + 1).is_some_and(|b| is_qdtext(*b)) => i += 2,
+            b'\\' => return None,
+            b if is_qdtext(b) => i += 1,
+            _ => return None,
+        }
+    }
+}
+
+// chunk-ext = *( BWS ";" BWS ext-name [ BWS "=" BWS ext-val ] ), checked
+// only so nothing else, e.g. a bare LF, hides in there.
+fn is_chunk_ext(mut ext: &[u8]) -> bool {
+    loop {
+        ext = trim_ows(ext);
+        let rest = match ext.split_first() {
+            None => return true,
+            Some((b';', rest)) => rest,
+            Some(_) => return false,
+        };
+        let rest = match split_token(trim_ows(rest)) {
+            Some((_, rest)) => trim_ows(rest),
+            None => return false,
+        };
+        ext = match rest.split_first() {
+            Some((b'=', value)) => {
+                let value = trim_ows(value);
+                let rest = match value.first() {
+                    Some(b'"') => skip_quoted_string(value),
+                    _ => split_token(value).map(|(_, rest)| rest),
+                };
+                match rest {
+                    Some(rest) => rest,
+                    None => return false,
+                }
+            }
+            _ => rest,
+        };
+    }
+}
+
+// e.g. : 1a;name=value
+fn parse_chunk_size(buffer: &[u8]) -> Result<(Option<usize>, usize), ParseError> {
+    let error_malformed_chunk_size = ParseError::MalformedChunk { offset: 0 };
+
+    let index = match find_crlf(buffer) {
+        Some(i) => i,
+        None => return Ok((None, 0)),
+    };
+
+    // Chunk extensions carry no meaning for us and are skipped, once they
+    // are known to be well formed.
+    let line = &buffer[..index];
+    let size_end = line.iter().position(|b| *b == b';').unwrap_or(line.len());
+    if !is_chunk_ext(&line[size_end..]) {
+        return Err(error_malformed_chunk_size);
+    }
+    let size = match line[..size_end]
+        .iter()
+        .rposition(|b| *b != b' ' && *b != b'\t')
+    {
+        Some(last) => &line[..=last],
+        None => return Err(error_malformed_chunk_size),
+    };
+
+    let mut n: usize = 0;
+    for b in size {
+        let digit = match (*b as char).to_digit(16) {
+            Some(d) => d as usize,
+            None => return Err(error_malformed_chunk_size),
+        };
+        n = match n.checked_mul(16).and_then(|n| n.checked_add(digit)) {
+            Some(n) => n,
+            None => return Err(error_malformed_chunk_size),
+        };
+    }
+
+    Ok((Some(n), index + "\r\n".len()))
+}
+
+#[derive(Clone, Copy)]
+pub(crate) enum Framing {
+    Length(usize),
+    Chunked,
+    // Only responses, the body runs until the peer closes the connection.
+    Close,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum DecoderState {
+    Length { remaining: usize },
+    ChunkSize,
+    ChunkData { remaining: usize },
+    ChunkDataEnd,
+    Trailers,
+    UntilClose,
+    Done,
+}
+
+/// Separates the body of one request from its framing.
+pub(crate) struct Decoder {
+    state: DecoderState,
+    limits: Limits,
+    // Body bytes handed out so far.
+    read: usize,
+    // Bytes of the request consumed so far, to report error offsets.
+    parsed: usize,
+    // Trailers count towards the header section's limits.
+    header_size: HeaderSize,
+    trailers: Headers,
+}
+
+impl Decoder {
+    pub(crate) fn new(
+        framing: Framing,
+        limits: Limits,
+        parsed: usize,
+        header_size: HeaderSize,
+    ) -> Decoder {
+        let state = match framing {
+            Framing::Length(0) => DecoderState::Done,
+            Framing::Length(n) => DecoderState::Length { remaining: n },
+            Framing::Chunked => DecoderState::ChunkSize,
+            Framing::Close => DecoderState::UntilClose,
+        };
+
+        Decoder {
+            state,
+            limits,
+            read: 0,
+            parsed,
+            header_size,
+            trailers: Headers::new(),
+        }
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.state == DecoderState::Done
+    }
+
+    pub(crate) fn parsed(&self) -> usize {
+        self.parsed
+    }
+
+    /// Ends a body that runs until the connection closes. Any other body
+    /// closed early is cut short.
+    pub(crate) fn close(&mut self) -> bool {
+        if self.state != DecoderState::UntilClose {
+            return false;
+        }
+        self.state = DecoderState::Done;
+        true
+    }
+
+    /// Body bytes left, when the framing says up front.
+    pub(crate) fn remaining(&self) -> Option<usize> {
+        match self.state {
+            DecoderState::Length { remaining } => Some(remaining),
+            DecoderState::Done => Some(0),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn trailers(&self) -> &Headers {
+        &self.trailers
+    }
+
+    pub(crate) fn into_trailers(self) -> Headers {
+        self.trailers
+    }
+
+    fn take(&mut self, buffer: &mut BytesMut, remaining: usize, max: usize) -> Option<Bytes> {
+        let n = min(min(buffer.len(), remaining), max);
+        if n == 0 {
+            return None;
+        }
+
+        self.parsed += n;
+        self.read += n;
+        Some(buffer.split_to(n).freeze())
+    }
+
+    /// Takes up to `max` bytes of body data off the front of `buffer`,
+    /// along with any framing in the way. `None` means it needs more input,
+    /// or the body is done.
+    pub(crate) fn decode(
+        &mut self,
+        buffer: &mut BytesMut,
+        max: usize,
+    ) -> Result<Option<Bytes>, ParseError> {
+        loop {
+            let offset = self.parsed;
+            let len = buffer.len();
+
+            match self.state {
+                DecoderState::Length { remaining } => {
+                    let data = match self.take(buffer, remaining, max) {
+                        Some(data) => data,
+                        None => return Ok(None),
+                    };
+                    self.state = match remaining - data.len() {
+                        0 => DecoderState::Done,
+                        remaining => DecoderState::Length { remaining },
+                    };
+                    return Ok(Some(data));
+                }
+                DecoderState::ChunkSize => match parse_chunk_size(buffer) {
+                    Ok((Some(0), bytes_parsed)) => {
+                        buffer.advance(bytes_parsed);
+                        self.state = DecoderState::Trailers;
+                    }
+                    Ok((Some(size), bytes_parsed)) => {
+                        if size > self.limits.max_body_size - self.read {
+                            return Err(ParseError::BodyTooLarge);
+                        }
+                        buffer.advance(bytes_parsed);
+                        self.state = DecoderState::ChunkData { remaining: size };
+                    }
+                    Ok((None, _)) if len > MAX_CHUNK_SIZE_LINE => {
+                        return Err(ParseError::MalformedChunk { offset });
+                    }
+                    Ok((None, _)) => return Ok(None),
+                    Err(e) => return Err(e.shift(offset)),
+                },
+                DecoderState::ChunkData { remaining } => {
+                    let data = match self.take(buffer, remaining, max) {
+                        Some(data) => data,
+                        None => return Ok(None),
+                    };
+                    self.state = match remaining - data.len() {
+                        0 => DecoderState::ChunkDataEnd,
+                        remaining => DecoderState::ChunkData { remaining },
+                    };
+                    return Ok(Some(data));
+                }
+                DecoderState::ChunkDataEnd => {
+                    if len < 2 {
+                        return Ok(None);
+                    }
+                    if &buffer[..2] != b"\r\n" {
+                        return Err(ParseError::MalformedChunk { offset });
+                    }
+                    buffer.advance(2);
+                    self.state = DecoderState::ChunkSize;
+                }
+                DecoderState::Trailers => {
+                    let done = match self.header_size.parse_fields(
+                        &mut self.trailers,
+                        &self.limits,
+                        buffer,
+                    ) {
+                        Ok(done) => done,
+                        Err(e) => return Err(e.shift(offset)),
+                    };
+
+                    if done {
+                        self.state = DecoderState::Done;
+                    }
+                }
+                DecoderState::UntilClose => return Ok(self.take(buffer, usize::MAX, max)),
+                DecoderState::Done => return Ok(None),
+            }
+
+            self.parsed += len - buffer.len();
+            if buffer.len() == len {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+struct State {
+    // Only present until the server takes the connection back.
+    reader: Option<RequestReader<Connection>>,
+    decoder: Decoder,
+    deadline: Option<Pin<Box<Sleep>>>,
+    // Until the first read, for a client waiting on `100 Continue`.
+    interim: Option<Continue>,
+    // A body that failed to read keeps failing the same way.
+    error: Option<ParseError>,
+}
+
+impl State {
+    fn poll_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ParseError>> {
+        if let Some(e) = &self.error {
+            return Poll::Ready(Err(e.clone()));
+        }
+
+        match ready!(self.poll_next(cx, max)) {
+            Ok(data) => Poll::Ready(Ok(data)),
+            Err(e) => {
+                self.error = Some(e.clone());
+                Poll::Ready(Err(e))
+            }
+        }
+    }
+
+    fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ParseError>> {
+        if let Some(interim) = self.interim.as_mut() {
+            if !self.decoder.is_done() {
+                ready!(interim.poll_send(cx))?;
+            }
+            self.interim = None;
+        }
+
+        let reader = match self.reader.as_mut() {
+            Some(r) => r,
+            None => return Poll::Ready(Err(Error::from(ErrorKind::BrokenPipe).into())),
+        };
+        reader.poll_body(&mut self.decoder, &mut self.deadline, cx, max)
+    }
+}
+
+enum Kind {
+    Full { data: Bytes, trailers: Headers },
+    Streaming(Arc<Mutex<State>>),
+}
+
+/// A request body. The server hands it to the handler unread, and it is
+/// pulled off the connection as the handler reads it, with the framing and
+/// the body size limit taken care of. Whatever the handler leaves unread is
+/// discarded once it returns.
+pub struct Body {
+    kind: Kind,
+}
+
+/// The server's side of a streaming [`Body`], used to get the connection
+/// back once the handler is done with it.
+pub(crate) struct PendingBody {
+    state: Arc<Mutex<State>>,
+}
+
+impl Body {
+    /// `interim` is sent before the body is first read from the
+    /// connection.
+    pub(crate) fn streaming(
+        reader: RequestReader<Connection>,
+        decoder: Decoder,
+        interim: Option<Continue>,
+    ) -> (Body, PendingBody) {
+        let state = Arc::new(Mutex::new(State {
+            deadline: None,
+            reader: Some(reader),
+            decoder,
+            interim,
+            error: None,
+        }));
+
+        (
+            Body {
+                kind: Kind::Streaming(Arc::clone(&state)),
+            },
+            PendingBody { state },
+        )
+    }
+
+    pub(crate) fn buffered(data: Vec<u8>, trailers: Headers) -> Body {
+        Body {
+            kind: Kind::Full {
+                data: Bytes::from(data),
+                trailers,
+            },
+        }
+    }
+
+    /// The next piece of the body, or `None` once it has all been read.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, ParseError> {
+        poll_fn(|cx| self.poll_chunk(cx, usize::MAX)).await
+    }
+
+    /// Reads the rest of the body into memory. Meant for bodies known to be
+    /// small, as the body size limit is all that bounds it.
+    pub async fn collect(&mut self) -> Result<Vec<u8>, ParseError> {
+        let mut body = Vec::new();
+        while let Some(data) = self.chunk().await? {
+            body.extend_from_slice(&data);
+        }
+
+        Ok(body)
+    }
+
+    // What is left to read, when that is known without reading it.
+    pub(crate) fn remaining(&self) -> Option<usize> {
+        match &self.kind {
+            Kind::Full { data, .. } => Some(data.len()),
+            Kind::Streaming(state) => state.lock().unwrap().decoder.remaining(),
+        }
+    }
+
+    /// The trailer fields of a chunked body. Empty until the body has been
+    /// read to the end.
+    pub fn trailers(&self) -> Headers {
+        match &self.kind {
+            Kind::Full { trailers, .. } => trailers.clone(),
+            Kind::Streaming(state) => state.lock().unwrap().decoder.trailers.clone(),
+        }
+    }
+
+    fn poll_chunk(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ParseError>> {
+        match &mut self.kind {
+            Kind::Full { data, .. } if data.is_empty() => Poll::Ready(Ok(None)),
+            Kind::Full { data, .. } => {
+                let n = min(data.len(), max);
+                Poll::Ready(Ok(Some(data.split_to(n))))
+            }
+            Kind::Streaming(state) => state.lock().unwrap().poll_chunk(cx, max),
+        }
+    }
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::buffered(Vec::new(), Headers::new())
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Self {
+        Body::buffered(data, Headers::new())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(data: &str) -> Self {
+        Body::buffered(data.as_bytes().to_vec(), Headers::new())
+    }
+}
+
+impl From<String> for Body {
+    fn from(data: String) -> Self {
+        Body::buffered(data.into_bytes(), Headers::new())
+    }
+}
+
+impl AsyncRead for Body {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<Result<(), Error>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let max = buf.remaining();
+        if let Some(data) = ready!(self.get_mut().poll_chunk(cx, max))? {
+            buf.put_slice(&data);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl PendingBody {
+    /// Whether the client is still holding the body back, waiting on a
+    /// `100 Continue` that never went out.
+    pub(crate) fn awaits_continue(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.interim.is_some() && !state.decoder.is_done()
+    }
+
+    /// What went wrong reading the body, if the handler ran into anything.
+    pub(crate) fn error(&self) -> Option<ParseError> {
+        self.state.lock().unwrap().error.clone()
+    }
+
+    /// Gives up on the rest of the body, and with it the connection.
+    pub(crate) fn abandon(self) {
+        self.state.lock().unwrap().reader = None;
+    }
+
+    /// Reads past whatever the handler left of the body and hands the
+    /// connection back, ready for the next request. Fails if the body
+    /// couldn't be read, in which case the connection can't be reused.
+    pub(crate) async fn finish(self) -> Result<RequestReader<Connection>, ParseError> {
+        let drained = poll_fn(|cx| {
+            let mut state = self.state.lock().unwrap();
+            while ready!(state.poll_chunk(cx, usize::MAX))?.is_some() {}
+            Poll::Ready(Ok::<(), ParseError>(()))
+        })
+        .await;
+
+        // A body the handler kept hold of can't read any further.
+        let reader = self.state.lock().unwrap().reader.take();
+        drained?;
+        reader.ok_or_else(|| Error::from(ErrorKind::BrokenPipe).into())
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+
+use crate::response::StatusCode;
+
+/// Why a request couldn't be read. Offsets count bytes from the start of the
+/// request and point at the start of the component that failed.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The request line isn't `method SP request-target SP HTTP-version`.
+    MalformedRequestLine {
+        offset: usize,
+    },
+    /// The method isn't a token.
+    InvalidMethod {
+        offset: usize,
+    },
+    /// The target doesn't parse, or its form doesn't go with the method.
+    MalformedTarget {
+        offset: usize,
+    },
+    MalformedVersion {
+        offset: usize,
+    },
+    /// Well formed, but not HTTP/1.x.
+    UnsupportedVersion {
+        offset: usize,
+    },
+    /// A header or trailer field line that doesn't parse.
+    MalformedHeader {
+        offset: usize,
+    },
+    MalformedContentLength {
+        offset: usize,
+    },
+    /// `chunked` isn't the last transfer coding, or appears more than once,
+    /// or the request is HTTP/1.0.
+    MalformedTransferEncoding {
+        offset: usize,
+    },
+    /// Transfer codings other than `chunked`.
+    UnsupportedTransferEncoding {
+        offset: usize,
+    },
+    /// Both `Transfer-Encoding` and `Content-Length` were sent.
+    ConflictingFraming {
+        offset: usize,
+    },
+    /// `Host` was sent more than once, or not at all in an HTTP/1.1
+    /// request.
+    MalformedHost {
+        offset: usize,
+    },
+    /// A chunk size line that doesn't parse, or chunk data that runs past
+    /// its size.
+    MalformedChunk {
+        offset: usize,
+    },
+    RequestLineTooLong,
+    HeaderSectionTooLarge,
+    TooManyHeaders,
+    BodyTooLarge,
+    /// The client closed the connection halfway through the request, after
+    /// sending `offset` bytes of it.
+    ConnectionClosed {
+        offset: usize,
+    },
+    /// The client took longer than the header or body timeout.
+    TimedOut,
+    Io(std::io::Error),
+}
+
+impl ParseError {
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            ParseError::MalformedRequestLine { offset }
+            | ParseError::InvalidMethod { offset }
+            | ParseError::MalformedTarget { offset }
+            | ParseError::MalformedVersion { offset }
+            | ParseError::UnsupportedVersion { offset }
+            | ParseError::MalformedHeader { offset }
+            | ParseError::MalformedContentLength { offset }
+            | ParseError::MalformedTransferEncoding { offset }
+            | ParseError::UnsupportedTransferEncoding { offset }
+            | ParseError::ConflictingFraming { offset }
+            | ParseError::MalformedHost { offset }
+            | ParseError::MalformedChunk { offset }
+            | ParseError::ConnectionClosed { offset } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The status to answer with, or `None` when the connection should just
+    /// be closed because nobody is left to read an answer.
+    pub fn status_code(&self) -> Option<StatusCode> {
+        match self {
+            // A method that isn't a token isn't a method we don't know, it's
+            // not a method at all.
+            ParseError::MalformedRequestLine { .. }
+            | ParseError::InvalidMethod { .. }
+            | ParseError::MalformedTarget { .. }
+            | ParseError::MalformedVersion { .. }
+            | ParseError::MalformedHeader { .. }
+            | ParseError::MalformedContentLength { .. }
+            | ParseError::MalformedTransferEncoding { .. }
+            | ParseError::ConflictingFraming { .. }
+            | ParseError::MalformedHost { .. }
+            | ParseError::MalformedChunk { .. } => Some(StatusCode::BadRequest),
+            ParseError::UnsupportedTransferEncoding { .. } => Some(StatusCode::NotImplemented),
+            ParseError::UnsupportedVersion { .. } => Some(StatusCode::HttpVersionNotSupported),
+            ParseError::RequestLineTooLong => Some(StatusCode::UriTooLong),
+            ParseError::HeaderSectionTooLarge | ParseError::TooManyHeaders => {
+                Some(StatusCode::RequestHeaderFieldsTooLarge)
+            }
+            ParseError::BodyTooLarge => Some(StatusCode::ContentTooLarge),
+            ParseError::TimedOut => Some(StatusCode::RequestTimeout),
+            ParseError::ConnectionClosed { .. } | ParseError::Io(_) => None,
+        }
+    }
+
+    // Moves an offset relative to a component to one relative to the
+    // request.
+    pub(crate) fn shift(self, by: usize) -> ParseError {
+        match self {
+            ParseError::MalformedRequestLine { offset } => ParseError::MalformedRequestLine {
+                offset: offset + by,
+            },
+            ParseError::InvalidMethod { offset } => ParseError::InvalidMethod {
+                offset: offset + by,
+            },
+            ParseError::MalformedTarget { offset } => ParseError::MalformedTarget {
+                offset: offset + by,
+            },
+            ParseError::MalformedVersion { offset } => ParseError::MalformedVersion {
+                offset: offset + by,
+            },
+            ParseError::UnsupportedVersion { offset } => ParseError::UnsupportedVersion {
+                offset: offset + by,
+            },
+            ParseError::MalformedHeader { offset } => ParseError::MalformedHeader {
+                offset: offset + by,
+            },
+            ParseError::MalformedContentLength { offset } => ParseError::MalformedContentLength {
+                offset: offset + by,
+            },
+            ParseError::MalformedTransferEncoding { offset } => {
+                ParseError::MalformedTransferEncoding {
+                    offset: offset + by,
+                }
+            }
+            ParseError::UnsupportedTransferEncoding { offset } => {
+                ParseError::UnsupportedTransferEncoding {
+                    offset: offset + by,
+                }
+            }
+            ParseError::ConflictingFraming { offset } => ParseError::ConflictingFraming {
+                offset: offset + by,
+            },
+            ParseError::MalformedHost { offset } => ParseError::MalformedHost {
+                offset: offset + by,
+            },
+            ParseError::MalformedChunk { offset } => ParseError::MalformedChunk {
+                offset: offset + by,
+            },
+            ParseError::ConnectionClosed { offset } => ParseError::ConnectionClosed {
+                offset: offset + by,
+            },
+            e => e,
+        }
+    }
+}
+
+// `io::Error` isn't `Clone`, so a cloned `Io` keeps only its kind and message.
+impl Clone for ParseError {
+    fn clone(&self) -> Self {
+        match self {
+            ParseError::MalformedRequestLine { offset } => {
+                ParseError::MalformedRequestLine { offset: *offset }
+            }
+            ParseError::InvalidMethod { offset } => ParseError::InvalidMethod { offset: *offset },
+            ParseError::MalformedTarget { offset } => {
+                ParseError::MalformedTarget { offset: *offset }
+            }
+            ParseError::MalformedVersion { offset } => {
+                ParseError::MalformedVersion { offset: *offset }
+            }
+            ParseError::UnsupportedVersion { offset } => {
+                ParseError::UnsupportedVersion { offset: *offset }
+            }
+            ParseError::MalformedHeader { offset } => {
+                ParseError::MalformedHeader { offset: *offset }
+            }
+            ParseError::MalformedContentLength { offset } => {
+                ParseError::MalformedContentLength { offset: *offset }
+            }
+            ParseError::MalformedTransferEncoding { offset } => {
+                ParseError::MalformedTransferEncoding { offset: *offset }
+            }
+            ParseError::UnsupportedTransferEncoding { offset } => {
+                ParseError::UnsupportedTransferEncoding { offset: *offset }
+            }
+            ParseError::ConflictingFraming { offset } => {
+                ParseError::ConflictingFraming { offset: *offset }
+            }
+            ParseError::MalformedHost { offset } => ParseError::MalformedHost { offset: *offset },
+            ParseError::MalformedChunk { offset } => ParseError::MalformedChunk { offset: *offset },
+            ParseError::RequestLineTooLong => ParseError::RequestLineTooLong,
+            ParseError::HeaderSectionTooLarge => ParseError::HeaderSectionTooLarge,
+            ParseError::TooManyHeaders => ParseError::TooManyHeaders,
+            ParseError::BodyTooLarge => ParseError::BodyTooLarge,
+            ParseError::ConnectionClosed { offset } => {
+                ParseError::ConnectionClosed { offset: *offset }
+            }
+            ParseError::TimedOut => ParseError::TimedOut,
+            ParseError::Io(e) => ParseError::Io(std::io::Error::new(e.kind(), e.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            ParseError::MalformedRequestLine { .. } => "Malformed Request Line",
+            ParseError::InvalidMethod { .. } => "Invalid Request Method",
+            ParseError::MalformedTarget { .. } => "Malformed Request Target",
+            ParseError::MalformedVersion { .. } => "Malformed HTTP Version",
+            ParseError::UnsupportedVersion { .. } => "Unsupported HTTP Version",
+            ParseError::MalformedHeader { .. } => "Malformed Header",
+            ParseError::MalformedContentLength { .. } => "Malformed Content-Length Header",
+            ParseError::MalformedTransferEncoding { .. } => "Malformed Transfer-Encoding Header",
+            ParseError::UnsupportedTransferEncoding { .. } => "Unsupported Transfer Coding",
+            ParseError::ConflictingFraming { .. } => {
+                "Both Transfer-Encoding and Content-Length Present"
+            }
+            ParseError::MalformedHost { .. } => "Missing or Repeated Host Header",
+            ParseError::MalformedChunk { .. } => "Malformed Chunk",
+            ParseError::RequestLineTooLong => "Request Line Too Long",
+            ParseError::HeaderSectionTooLarge => "Header Section Too Large",
+            ParseError::TooManyHeaders => "Too Many Headers",
+            ParseError::BodyTooLarge => "Body Too Large",
+            ParseError::ConnectionClosed { .. } => "Connection Closed Before Request Complete",
+            ParseError::TimedOut => "Request Timeout",
+            ParseError::Io(e) => return write!(f, "{}", e),
+        };
+
+        match self.offset() {
+            Some(offset) => write!(f, "{} at byte {}", message, offset),
+            None => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ParseError {
+    fn from(e: std::io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+impl From<ParseError> for std::io::Error {
+    fn from(e: ParseError) -> Self {
+        if let ParseError::Io(e) = e {
+            return e;
+        }
+
+        let kind = match &e {
+            ParseError::ConnectionClosed { .. } => std::io::ErrorKind::UnexpectedEof,
+            ParseError::TimedOut => std::io::ErrorKind::TimedOut,
+            ParseError::UnsupportedVersion { .. } => std::io::ErrorKind::Unsupported,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, e)
+    }
+}
@@ -1,202 +1,667 @@
-use std::{cmp::min, io::Error};
+use std::future::poll_fn;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::{collections::HashMap, time::Duration};
 
-use crate::headers::Headers;
+use bytes::{Buf, Bytes, BytesMut};
+
+use crate::headers::{Headers, find_crlf, is_token, trim_ows};
+use crate::uri::{TargetForm, Uri};
 
 use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::time::{Instant, Sleep};
+
+mod body;
+mod error;
+
+pub use body::Body;
+pub(crate) use body::{Connection, Decoder, Framing};
+pub use error::ParseError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum RequestMethod {
     Get,
+    Head,
     Post,
     Put,
     Delete,
+    Connect,
+    Options,
+    Trace,
+    Patch,
+    /// Any other method token, e.g. WebDAV's `PROPFIND`.
+    Extension(String),
 }
 
 impl RequestMethod {
     fn from_str(method: &str) -> Option<RequestMethod> {
         match method {
             "GET" => Some(RequestMethod::Get),
+            "HEAD" => Some(RequestMethod::Head),
             "POST" => Some(RequestMethod::Post),
             "PUT" => Some(RequestMethod::Put),
             "DELETE" => Some(RequestMethod::Delete),
+            "CONNECT" => Some(RequestMethod::Connect),
+            "OPTIONS" => Some(RequestMethod::Options),
+            "TRACE" => Some(RequestMethod::Trace),
+            "PATCH" => Some(RequestMethod::Patch),
+            _ if is_token(method.as_bytes()) => Some(RequestMethod::Extension(method.to_string())),
             _ => None,
         }
     }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            RequestMethod::Get => "GET",
+            RequestMethod::Head => "HEAD",
+            RequestMethod::Post => "POST",
+            RequestMethod::Put => "PUT",
+            RequestMethod::Delete => "DELETE",
+            RequestMethod::Connect => "CONNECT",
+            RequestMethod::Options => "OPTIONS",
+            RequestMethod::Trace => "TRACE",
+            RequestMethod::Patch => "PATCH",
+            RequestMethod::Extension(method) => method,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+
+impl HttpVersion {
+    pub fn as_str(&self) -> &str {
+        match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+/// Upper bounds on what a client may send. Sizes are in bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Longest request line, without its CRLF.
+    pub max_request_line: usize,
+    /// Largest header section, all field lines and the blank line included.
+    /// Trailers count towards it too.
+    pub max_header_size: usize,
+    pub max_header_count: usize,
+    pub max_body_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_request_line: 8 * 1024,
+            max_header_size: 64 * 1024,
+            max_header_count: 100,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
 }
 
 pub struct RequestLine {
-    pub _http_version: String,
+    pub http_version: HttpVersion,
+    /// The request target exactly as it was sent.
     pub request_target: String,
-    pub _method: RequestMethod,
+    pub uri: Uri,
+    pub method: RequestMethod,
 }
 
 #[derive(PartialEq, Debug)]
 enum ParserState {
     StateRequestLine,
     StateHeaders,
-    StateBody,
     Done,
 }
 
+// Bytes and field lines of the header section and trailers so far.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct HeaderSize {
+    bytes: usize,
+    fields: usize,
+}
+
+impl HeaderSize {
+    // Called after each pass over the header section or trailers with the
+    // bytes and fields it took, and the size of the incomplete line left.
+    fn add(
+        &mut self,
+        limits: &Limits,
+        parsed: usize,
+        fields: usize,
+        incomplete: usize,
+    ) -> Result<(), ParseError> {
+        self.bytes += parsed;
+        self.fields += fields;
+
+        if self.bytes + incomplete > limits.max_header_size {
+            return Err(ParseError::HeaderSectionTooLarge);
+        }
+        if self.fields > limits.max_header_count {
+            return Err(ParseError::TooManyHeaders);
+        }
+
+        Ok(())
+    }
+
+    // Takes what it can of a header section or trailers off the front of
+    // `buffer`. True once the blank line ending it is in.
+    pub(crate) fn parse_fields(
+        &mut self,
+        headers: &mut Headers,
+        limits: &Limits,
+        buffer: &mut BytesMut,
+    ) -> Result<bool, ParseError> {
+        let len = buffer.len();
+        let fields = headers.len();
+        let done = headers.parse_buf(buffer)?;
+        let incomplete = if done { 0 } else { buffer.len() };
+        self.add(
+            limits,
+            len - buffer.len(),
+            headers.len() - fields,
+            incomplete,
+        )?;
+
+        Ok(done)
+    }
+}
+
 pub struct Request {
     pub request_line: RequestLine,
     pub headers: Headers,
-    pub body: Vec<u8>,
+    pub body: Body,
+    /// Path parameters filled in by the [`Router`](crate::router::Router).
+    pub params: HashMap<String, String>,
+    /// The client's address, filled in by the server.
+    pub peer_addr: Option<SocketAddr>,
     state: ParserState,
+    limits: Limits,
+    // Bytes of the request consumed so far, to report error offsets.
+    parsed: usize,
+    header_size: HeaderSize,
 }
 
-fn new_request() -> Request {
+fn new_request(limits: Limits) -> Request {
     Request {
         request_line: RequestLine {
-            _http_version: String::new(),
+            http_version: HttpVersion::Http11,
             request_target: String::new(),
-            _method: RequestMethod::Get,
+            uri: Uri::default(),
+            method: RequestMethod::Get,
         },
         headers: Headers::new(),
-        body: Vec::new(),
+        body: Body::default(),
+        params: HashMap::new(),
+        peer_addr: None,
         state: ParserState::StateRequestLine,
+        limits,
+        parsed: 0,
+        header_size: HeaderSize::default(),
     }
 }
 
+// e.g. : HTTP/1.1
+pub(crate) fn parse_http_version(version: &[u8]) -> Option<(u8, u8)> {
+    let digits = version.strip_prefix(b"HTTP/")?;
+    if digits.len() != 3 || digits[1] != b'.' {
+        return None;
+    }
+    if !digits[0].is_ascii_digit() || !digits[2].is_ascii_digit() {
+        return None;
+    }
+
+    Some((digits[0] - b'0', digits[2] - b'0'))
+}
+
 // e.g. : GET /coffee HTTP/1.1
-fn parse_request_line(request: &[u8]) -> Result<(Option<RequestLine>, usize), Error> {
-    let error_malformed_request_line =
-        Error::new(std::io::ErrorKind::InvalidData, "Malformed Request Line");
-    let error_unsupported_http_version =
-        Error::new(std::io::ErrorKind::InvalidData, "Unsupported HTTP Version");
-    let error_invalid_request_method =
-        Error::new(std::io::ErrorKind::InvalidData, "Invalid Request Method");
-
-    let request_string = match std::str::from_utf8(request) {
-        Ok(s) => s,
-        Err(_) => return Err(error_malformed_request_line),
-    };
-    let index = match request_string.find("\r\n") {
+fn parse_request_line(request: &[u8]) -> Result<(Option<RequestLine>, usize), ParseError> {
+    let index = match find_crlf(request) {
         Some(i) => i,
         None => return Ok((None, 0)),
     };
 
-    let request_line = &request_string[..index];
+    let request_line = &request[..index];
     let read = index + "\r\n".len();
 
-    let parts = request_line.split_whitespace().collect::<Vec<&str>>();
-    if parts.len() != 3 {
-        return Err(error_malformed_request_line);
-    }
+    let mut parts = request_line
+        .split(|b| *b == b' ' || *b == b'\t')
+        .filter(|p| !p.is_empty());
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(ParseError::MalformedRequestLine { offset: 0 }),
+    };
+    let offset_of = |part: &[u8]| part.as_ptr() as usize - request_line.as_ptr() as usize;
 
-    let method = match RequestMethod::from_str(parts[0]) {
+    let method = match std::str::from_utf8(method)
+        .ok()
+        .and_then(RequestMethod::from_str)
+    {
         Some(m) => m,
-        None => return Err(error_invalid_request_method),
+        None => {
+            return Err(ParseError::InvalidMethod {
+                offset: offset_of(method),
+            });
+        }
     };
 
-    let http_parts = parts[2].split("/").collect::<Vec<&str>>();
-    if http_parts[0] != "HTTP" || http_parts[1] != "1.1" {
-        return Err(error_unsupported_http_version);
+    let error_malformed_target = ParseError::MalformedTarget {
+        offset: offset_of(target),
+    };
+    let request_target = match std::str::from_utf8(target) {
+        Ok(t) => t,
+        Err(_) => return Err(error_malformed_target),
+    };
+    let uri = match Uri::parse(request_target) {
+        Ok(uri) => uri,
+        Err(_) => return Err(error_malformed_target),
+    };
+    // CONNECT is the only method to use authority-form and the only one
+    // that can't use anything else, OPTIONS the only one to allow `*`.
+    let form_allowed = match uri.form {
+        TargetForm::Authority => method == RequestMethod::Connect,
+        TargetForm::Asterisk => method == RequestMethod::Options,
+        _ => method != RequestMethod::Connect,
+    };
+    if !form_allowed {
+        return Err(error_malformed_target);
     }
 
+    let version_offset = offset_of(version);
+    let http_version = match parse_http_version(version) {
+        Some((1, 0)) => HttpVersion::Http10,
+        // Later 1.x minor versions are backwards compatible with 1.1.
+        Some((1, _)) => HttpVersion::Http11,
+        Some(_) => {
+            return Err(ParseError::UnsupportedVersion {
+                offset: version_offset,
+            });
+        }
+        None => {
+            return Err(ParseError::MalformedVersion {
+                offset: version_offset,
+            });
+        }
+    };
+
     Ok((
         Some(RequestLine {
-            _http_version: http_parts[1].to_string(),
-            request_target: parts[1].to_string(),
-            _method: method,
+            http_version,
+            request_target: request_target.to_string(),
+            uri,
+            method,
         }),
         read,
     ))
 }
 
+// Only `chunked` is understood, and it has to come last, exactly once.
+// Errors point at `offset`, the end of the header section.
+pub(crate) fn is_chunked(
+    headers: &Headers,
+    version: HttpVersion,
+    offset: usize,
+) -> Result<bool, ParseError> {
+    let values = headers.get_all_bytes("transfer-encoding");
+    if values.is_empty() {
+        return Ok(false);
+    }
+
+    let error_malformed = ParseError::MalformedTransferEncoding { offset };
+    // HTTP/1.0 has no transfer codings, so whatever sits between us and
+    // the peer may frame the body differently.
+    if version == HttpVersion::Http10 {
+        return Err(error_malformed);
+    }
+
+    let codings = values
+        .iter()
+        .flat_map(|value| value.split(|b| *b == b','))
+        .map(trim_ows)
+        .filter(|coding| !coding.is_empty())
+        .collect::<Vec<&[u8]>>();
+    let (last, rest) = match codings.split_last() {
+        Some(codings) => codings,
+        None => return Err(error_malformed),
+    };
+    if !last.eq_ignore_ascii_case(b"chunked") {
+        return Err(error_malformed);
+    }
+    if rest
+        .iter()
+        .any(|coding| !is_token(coding) || coding.eq_ignore_ascii_case(b"chunked"))
+    {
+        return Err(error_malformed);
+    }
+    if !rest.is_empty() {
+        return Err(ParseError::UnsupportedTransferEncoding { offset });
+    }
+
+    Ok(true)
+}
+
+// Repeated values, in one field or several, are fine as long as they all
+// agree.
+pub(crate) fn content_length(
+    headers: &Headers,
+    offset: usize,
+) -> Result<Option<usize>, ParseError> {
+    let error_malformed = ParseError::MalformedContentLength { offset };
+
+    let mut content_length = None;
+    for value in headers.get_all_bytes("content-length") {
+        for n in value.split(|b| *b == b',').map(trim_ows) {
+            if n.is_empty() || !n.iter().all(u8::is_ascii_digit) {
+                return Err(error_malformed);
+            }
+            let n = match std::str::from_utf8(n).ok().and_then(|n| n.parse().ok()) {
+                Some(n) => n,
+                None => return Err(error_malformed),
+            };
+            if content_length.is_some_and(|length| length != n) {
+                return Err(error_malformed);
+            }
+            content_length = Some(n);
+        }
+    }
+
+    Ok(content_length)
+}
+
 impl Request {
-    fn parse(&mut self, buffer: &[u8]) -> Result<usize, Error> {
-        let mut remaining = buffer;
-        let mut total_read = 0;
+    // HTTP/1.1 requests name their host exactly once, RFC 9112 section 3.2.
+    // Two of them could be read as either.
+    fn check_host(&self) -> Result<(), ParseError> {
+        let hosts = self.headers.get_all_bytes("host").len();
+        if hosts > 1 || (hosts == 0 && self.request_line.http_version == HttpVersion::Http11) {
+            return Err(ParseError::MalformedHost {
+                offset: self.parsed,
+            });
+        }
+        Ok(())
+    }
+
+    // Decides how the body is framed once the header section is in, per
+    // RFC 9112 section 6.3. Anything that could be read two ways is refused.
+    fn decoder(&self) -> Result<Decoder, ParseError> {
+        if self.headers.contains("transfer-encoding") && self.headers.contains("content-length") {
+            return Err(ParseError::ConflictingFraming {
+                offset: self.parsed,
+            });
+        }
+
+        let version = self.request_line.http_version;
+        let framing = if is_chunked(&self.headers, version, self.parsed)? {
+            Framing::Chunked
+        } else {
+            match content_length(&self.headers, self.parsed)? {
+                Some(n) if n > self.limits.max_body_size => return Err(ParseError::BodyTooLarge),
+                Some(n) => Framing::Length(n),
+                None => Framing::Length(0),
+            }
+        };
+
+        Ok(Decoder::new(
+            framing,
+            self.limits,
+            self.parsed,
+            self.header_size,
+        ))
+    }
+
+    // Takes what it can parse of the request line and header section off the
+    // front of `buffer`.
+    fn parse(&mut self, buffer: &mut BytesMut) -> Result<(), ParseError> {
+        // Empty lines ahead of the request line are ignored, e.g. a CRLF sent
+        // after the last body, RFC 9112 section 2.2. Only so many, though.
+        while self.state == ParserState::StateRequestLine && buffer.starts_with(b"\r\n") {
+            if self.parsed >= MAX_EMPTY_LINES * "\r\n".len() {
+                return Err(ParseError::MalformedRequestLine {
+                    offset: self.parsed,
+                });
+            }
+            buffer.advance("\r\n".len());
+            self.parsed += "\r\n".len();
+        }
 
         loop {
-            let bytes_parsed = match self.state {
-                ParserState::StateRequestLine => match parse_request_line(remaining) {
-                    Ok((request_line, bytes_parsed)) => {
-                        if bytes_parsed == 0 {
-                            break;
-                        }
-                        self.request_line = request_line.unwrap();
-                        self.state = ParserState::StateHeaders;
-                        bytes_parsed
+            let offset = self.parsed;
+            let len = buffer.len();
+
+            match self.state {
+                ParserState::StateRequestLine => {
+                    let line_len = find_crlf(buffer).unwrap_or(buffer.len());
+                    if line_len > self.limits.max_request_line {
+                        return Err(ParseError::RequestLineTooLong);
                     }
-                    Err(e) => return Err(e),
-                },
-                ParserState::StateHeaders => match self.headers.parse(remaining) {
-                    Ok((done, bytes_parsed)) => {
-                        if done {
-                            self.state = ParserState::StateBody;
+
+                    match parse_request_line(buffer) {
+                        Ok((Some(request_line), bytes_parsed)) => {
+                            buffer.advance(bytes_parsed);
+                            self.request_line = request_line;
+                            self.state = ParserState::StateHeaders;
                         }
-                        bytes_parsed
+                        Ok((None, _)) => break,
+                        Err(e) => return Err(e.shift(offset)),
                     }
-                    Err(e) => return Err(e),
-                },
-                ParserState::StateBody => {
-                    let content_length = if let Some(c) = self.headers.get("content-length") {
-                        match c.parse::<usize>() {
-                            Ok(t) => t,
-                            Err(_) => {
-                                return Err(Error::new(
-                                    std::io::ErrorKind::InvalidData,
-                                    "Malformed Content-Length Header",
-                                ));
-                            }
-                        }
-                    } else {
-                        self.state = ParserState::Done;
-                        break;
-                    };
-
-                    let to_read = min(remaining.len(), content_length - self.body.len());
-                    self.body.extend_from_slice(&remaining[..to_read]);
+                }
+                ParserState::StateHeaders => {
+                    let done =
+                        match self
+                            .header_size
+                            .parse_fields(&mut self.headers, &self.limits, buffer)
+                        {
+                            Ok(done) => done,
+                            Err(e) => return Err(e.shift(offset)),
+                        };
 
-                    if self.body.len() == content_length {
+                    if done {
                         self.state = ParserState::Done;
                     }
-
-                    to_read
                 }
                 ParserState::Done => break,
-            };
+            }
 
-            if bytes_parsed == 0 {
+            self.parsed += len - buffer.len();
+            if buffer.len() == len {
                 break;
             }
-
-            total_read += bytes_parsed;
-            remaining = &remaining[bytes_parsed..];
         }
 
-        Ok(total_read)
+        Ok(())
     }
 }
 
-pub async fn request_from_reader<R>(mut stream: R) -> Result<Request, Error>
+const READ_SIZE: usize = 1024;
+const MAX_EMPTY_LINES: usize = 8;
+
+/// How long the reader waits on a client. `None` waits forever.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeouts {
+    /// Until the first byte of a request arrives. Running out closes the
+    /// connection quietly.
+    pub idle: Option<Duration>,
+    /// From the first byte until the end of the header section.
+    pub header: Option<Duration>,
+    /// Between reads of the body, while waiting on the client to send more
+    /// of it.
+    pub body: Option<Duration>,
+}
+
+pub struct RequestReader<R> {
+    stream: R,
+    // Grows as needed, the parser's limits keep it bounded. Parsed header
+    // fields keep pointing into it.
+    buffer: BytesMut,
+    limits: Limits,
+    timeouts: Timeouts,
+}
+
+impl<R> RequestReader<R>
 where
     R: AsyncRead + Unpin,
 {
-    let mut buffer = [0u8; 1024];
-    let mut request = new_request();
-    let mut buf_len = 0;
-
-    while request.state != ParserState::Done {
-        let bytes_read = match stream.read(&mut buffer[buf_len..]).await {
-            Ok(n) => n,
-            // TODO: Should resolve the errors
-            Err(e) => return Err(e),
-        };
-        buf_len += bytes_read;
+    pub fn new(stream: R) -> RequestReader<R> {
+        RequestReader::with_limits(stream, Limits::default())
+    }
+
+    pub fn with_limits(stream: R, limits: Limits) -> RequestReader<R> {
+        RequestReader {
+            stream,
+            buffer: BytesMut::with_capacity(READ_SIZE),
+            limits,
+            timeouts: Timeouts::default(),
+        }
+    }
 
-        let read_bytes = match request.parse(&buffer[..buf_len]) {
-            Ok(n) => n,
-            Err(e) => return Err(e),
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.stream
+    }
+
+    /// Reads the next request off the connection, body and all. Returns
+    /// `None` when the client closed the connection, or went idle for too
+    /// long, between requests.
+    pub async fn next_request(&mut self) -> Result<Option<Request>, ParseError> {
+        let (mut request, mut decoder) = match self.next_head().await? {
+            Some(head) => head,
+            None => return Ok(None),
         };
 
-        buffer.copy_within(read_bytes..buf_len, 0);
-        buf_len -= read_bytes;
+        let mut deadline = None;
+        let mut body = Vec::new();
+        while let Some(data) =
+            poll_fn(|cx| self.poll_body(&mut decoder, &mut deadline, cx, usize::MAX)).await?
+        {
+            body.extend_from_slice(&data);
+        }
+        request.body = Body::buffered(body, decoder.into_trailers());
+
+        Ok(Some(request))
     }
 
-    Ok(request)
+    // Reads up to the end of the next request's header section, leaving the
+    // body to the returned decoder.
+    pub(crate) async fn next_head(&mut self) -> Result<Option<(Request, Decoder)>, ParseError> {
+        let mut request = new_request(self.limits);
+        let mut idle = true;
+        let mut deadline = deadline_after(self.timeouts.idle);
+
+        // Bytes left over from a previous read (e.g. a pipelined request) are
+        // parsed before reading from the stream again.
+        loop {
+            request.parse(&mut self.buffer)?;
+
+            if request.state == ParserState::Done {
+                break;
+            }
+
+            let started = !self.buffer.is_empty() || request.state != ParserState::StateRequestLine;
+            if idle && started {
+                idle = false;
+                deadline = deadline_after(self.timeouts.header);
+            }
+
+            self.buffer.reserve(READ_SIZE);
+            let read = self.stream.read_buf(&mut self.buffer);
+            let read = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, read).await {
+                    Ok(res) => res,
+                    Err(_) if idle => return Ok(None),
+                    Err(_) => {
+                        return Err(ParseError::TimedOut);
+                    }
+                },
+                None => read.await,
+            };
+            let bytes_read = match read {
+                Ok(n) => n,
+                Err(e) => return Err(ParseError::Io(e)),
+            };
+            if bytes_read == 0 {
+                if idle {
+                    return Ok(None);
+                }
+                return Err(ParseError::ConnectionClosed {
+                    offset: request.parsed + self.buffer.len(),
+                });
+            }
+        }
+
+        request.check_host()?;
+        let decoder = request.decoder()?;
+        Ok(Some((request, decoder)))
+    }
+
+    // Hands out the next piece of a body, reading from the stream whenever
+    // the buffer runs dry. `None` once the body is done. The deadline is
+    // set when the stream has nothing to give and cleared when it does, so
+    // only time spent waiting on the client counts.
+    fn poll_body(
+        &mut self,
+        decoder: &mut Decoder,
+        deadline: &mut Option<Pin<Box<Sleep>>>,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<Result<Option<Bytes>, ParseError>> {
+        loop {
+            if let Some(data) = decoder.decode(&mut self.buffer, max)? {
+                return Poll::Ready(Ok(Some(data)));
+            }
+            if decoder.is_done() {
+                return Poll::Ready(Ok(None));
+            }
+
+            self.buffer.reserve(READ_SIZE);
+            let read = {
+                let read = std::pin::pin!(self.stream.read_buf(&mut self.buffer));
+                read.poll(cx)
+            };
+            match read {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(ParseError::ConnectionClosed {
+                        offset: decoder.parsed() + self.buffer.len(),
+                    }));
+                }
+                Poll::Ready(Ok(_)) => *deadline = None,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(ParseError::Io(e))),
+                Poll::Pending => {
+                    let timeout = match self.timeouts.body {
+                        Some(timeout) => timeout,
+                        None => return Poll::Pending,
+                    };
+                    let deadline =
+                        deadline.get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+                    ready!(deadline.as_mut().poll(cx));
+                    return Poll::Ready(Err(ParseError::TimedOut));
+                }
+            }
+        }
+    }
+}
+
+fn deadline_after(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|t| Instant::now() + t)
+}
+
+pub async fn request_from_reader<R>(stream: R) -> Result<Request, ParseError>
+where
+    R: AsyncRead + Unpin,
+{
+    match RequestReader::new(stream).next_request().await {
+        Ok(Some(request)) => Ok(request),
+        Ok(None) => Err(ParseError::ConnectionClosed { offset: 0 }),
+        Err(e) => Err(e),
+    }
 }
 
 #[cfg(test)]
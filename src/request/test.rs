@@ -1,8 +1,9 @@
 use super::*;
 
+use crate::response::StatusCode;
 use std::pin::Pin;
 use std::task::{Context, Poll};
-use tokio::io::ReadBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadBuf};
 
 #[derive(Debug)]
 struct ChunkReader {
@@ -41,9 +42,9 @@ async fn good_get_request_line() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Get, result.request_line._method);
+    assert_eq!(RequestMethod::Get, result.request_line.method);
     assert_eq!("/", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
 }
 
 #[tokio::test]
@@ -59,9 +60,9 @@ async fn good_get_request_line_with_path() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Get, result.request_line._method);
+    assert_eq!(RequestMethod::Get, result.request_line.method);
     assert_eq!("/coffee", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
 }
 
 #[tokio::test]
@@ -91,14 +92,52 @@ async fn post_request_line() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Post, result.request_line._method);
+    assert_eq!(RequestMethod::Post, result.request_line.method);
     assert_eq!("/submit", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
+}
+
+#[tokio::test]
+async fn extension_method_request_line() {
+    let req_bytes = b"PROPFIND /data HTTP/1.1\r\nHost: localhost:42069\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
+    let result = request_from_reader(reader)
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(
+        RequestMethod::Extension("PROPFIND".to_string()),
+        result.request_line.method
+    );
+    assert_eq!("PROPFIND", result.request_line.method.as_str());
+}
+
+#[tokio::test]
+async fn standard_methods_request_line() {
+    for (name, target, method) in [
+        ("HEAD", "/data", RequestMethod::Head),
+        ("OPTIONS", "*", RequestMethod::Options),
+        ("PATCH", "/data", RequestMethod::Patch),
+        ("TRACE", "/data", RequestMethod::Trace),
+        ("CONNECT", "example.com:443", RequestMethod::Connect),
+    ] {
+        let req_bytes = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", name, target);
+        let result = request_from_reader(req_bytes.as_bytes())
+            .await
+            .expect("Failed to parse request");
+
+        assert_eq!(method, result.request_line.method);
+    }
 }
 
 #[tokio::test]
 async fn invalid_method_request_line() {
-    let req_bytes = b"FETCH /data HTTP/1.1\r\nHost: localhost:42069\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\n\r\n";
+    let req_bytes = b"FE(TCH) /data HTTP/1.1\r\nHost: localhost:42069\r\nUser-Agent: curl/7.81.0\r\nAccept: */*\r\n\r\n";
     let reader = ChunkReader {
         data: req_bytes.to_vec(),
         num_bytes_per_read: 3,
@@ -133,22 +172,16 @@ async fn valid_request_with_headers() {
         pos: 0,
     };
 
-    let mut result = request_from_reader(reader)
+    let result = request_from_reader(reader)
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Get, result.request_line._method);
+    assert_eq!(RequestMethod::Get, result.request_line.method);
     assert_eq!("/", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
-    assert_eq!(
-        result.headers.get("host"),
-        Some(&"localhost:42069".to_string())
-    );
-    assert_eq!(
-        result.headers.get("user-agent"),
-        Some(&"curl/7.81.0".to_string())
-    );
-    assert_eq!(result.headers.get("accept"), Some(&"*/*".to_string()));
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
+    assert_eq!(result.headers.get("host"), Some("localhost:42069"));
+    assert_eq!(result.headers.get("user-agent"), Some("curl/7.81.0"));
+    assert_eq!(result.headers.get("accept"), Some("*/*"));
 }
 
 #[tokio::test]
@@ -168,7 +201,7 @@ async fn malformed_header() {
 
 #[tokio::test]
 async fn empty_headers() {
-    let req_bytes = b"GET / HTTP/1.1\r\n\r\n";
+    let req_bytes = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
     let reader = ChunkReader {
         data: req_bytes.to_vec(),
         num_bytes_per_read: 5,
@@ -179,9 +212,9 @@ async fn empty_headers() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Get, result.request_line._method);
+    assert_eq!(RequestMethod::Get, result.request_line.method);
     assert_eq!("/", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
 }
 
 #[tokio::test]
@@ -197,14 +230,11 @@ async fn standard_body() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Post, result.request_line._method);
+    assert_eq!(RequestMethod::Post, result.request_line.method);
     assert_eq!("/submit", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
-    assert_eq!(
-        result.headers.get("content-length"),
-        Some(&"13".to_string())
-    );
-    assert_eq!(result.body, b"Hello, world!");
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
+    assert_eq!(result.headers.get("content-length"), Some("13"));
+    assert_eq!(result.body.collect().await.unwrap(), b"Hello, world!");
 }
 
 #[tokio::test]
@@ -217,9 +247,9 @@ async fn more_content_than_content_length() {
         pos: 0,
     };
 
-    let result = request_from_reader(reader).await.unwrap();
+    let mut result = request_from_reader(reader).await.unwrap();
 
-    assert_eq!(result.body, b"Hello");
+    assert_eq!(result.body.collect().await.unwrap(), b"Hello");
 }
 
 #[tokio::test]
@@ -249,11 +279,11 @@ async fn empty_body_with_content_length() {
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Post, result.request_line._method);
+    assert_eq!(RequestMethod::Post, result.request_line.method);
     assert_eq!("/submit", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
-    assert_eq!(result.headers.get("content-length"), Some(&"0".to_string()));
-    assert_eq!(result.body, b"");
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
+    assert_eq!(result.headers.get("content-length"), Some("0"));
+    assert_eq!(result.body.collect().await.unwrap(), b"");
 }
 
 #[tokio::test]
@@ -265,14 +295,14 @@ async fn empty_body_without_content_length() {
         pos: 0,
     };
 
-    let result = request_from_reader(reader)
+    let mut result = request_from_reader(reader)
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(RequestMethod::Get, result.request_line._method);
+    assert_eq!(RequestMethod::Get, result.request_line.method);
     assert_eq!("/", result.request_line.request_target);
-    assert_eq!("1.1", result.request_line._http_version);
-    assert_eq!(result.body, b"");
+    assert_eq!(HttpVersion::Http11, result.request_line.http_version);
+    assert_eq!(result.body.collect().await.unwrap(), b"");
 }
 
 #[tokio::test]
@@ -284,9 +314,733 @@ async fn body_without_content_length() {
         pos: 0,
     };
 
+    let mut result = request_from_reader(reader)
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(result.body.collect().await.unwrap(), b"");
+}
+
+#[tokio::test]
+async fn pipelined_requests_on_one_reader() {
+    let req_bytes = b"GET /first HTTP/1.1\r\nHost: localhost:42069\r\n\r\nPOST /second HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 7,
+        pos: 0,
+    };
+    let mut reader = RequestReader::new(reader);
+
+    let first = reader
+        .next_request()
+        .await
+        .expect("Failed to parse request")
+        .expect("Connection closed");
+    assert_eq!(RequestMethod::Get, first.request_line.method);
+    assert_eq!("/first", first.request_line.request_target);
+
+    let mut second = reader
+        .next_request()
+        .await
+        .expect("Failed to parse request")
+        .expect("Connection closed");
+    assert_eq!(RequestMethod::Post, second.request_line.method);
+    assert_eq!("/second", second.request_line.request_target);
+    assert_eq!(second.body.collect().await.unwrap(), b"Hello");
+
+    let result = reader.next_request().await;
+    assert!(matches!(result, Ok(None)));
+}
+
+#[tokio::test]
+async fn empty_lines_between_requests_are_skipped() {
+    let req_bytes = b"POST /first HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello\r\n\r\nGET /second HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    for num_bytes_per_read in [1, req_bytes.len()] {
+        let reader = ChunkReader {
+            data: req_bytes.to_vec(),
+            num_bytes_per_read,
+            pos: 0,
+        };
+        let mut reader = RequestReader::new(reader);
+
+        let mut first = reader.next_request().await.unwrap().unwrap();
+        assert_eq!("/first", first.request_line.request_target);
+        assert_eq!(first.body.collect().await.unwrap(), b"Hello");
+
+        let second = reader.next_request().await.unwrap().unwrap();
+        assert_eq!("/second", second.request_line.request_target);
+        assert!(matches!(reader.next_request().await, Ok(None)));
+    }
+
+    let req_bytes = [
+        &b"\r\n".repeat(9)[..],
+        b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    ]
+    .concat();
+    let result = request_from_reader(&req_bytes[..]).await;
+    assert!(matches!(
+        result,
+        Err(ParseError::MalformedRequestLine { offset: 16 })
+    ));
+}
+
+#[tokio::test]
+async fn chunked_body() {
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost:42069\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nHello, \r\n6\r\nworld!\r\n0\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
+    let mut result = request_from_reader(reader)
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(result.body.collect().await.unwrap(), b"Hello, world!");
+}
+
+#[tokio::test]
+async fn chunked_body_with_extensions_and_trailers() {
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost:42069\r\nTransfer-Encoding: chunked\r\n\r\n5;name=value\r\nHello\r\n8 ; flag ; q = \"a \\\"b\"\r\n, world!\r\n0\r\nDigest: abc123\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 5,
+        pos: 0,
+    };
+
+    let mut result = request_from_reader(reader)
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(result.body.collect().await.unwrap(), b"Hello, world!");
+    assert_eq!(result.body.trailers().get("digest"), Some("abc123"));
+}
+
+#[tokio::test]
+async fn invalid_chunk_size() {
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost:42069\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\nHello\r\n0\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
+    let result = request_from_reader(reader).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn chunk_longer_than_declared_size() {
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost:42069\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nHello\r\n0\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
+    let result = request_from_reader(reader).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn http_1_0_request_line() {
+    let req_bytes = b"GET /coffee HTTP/1.0\r\nHost: localhost:42069\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
     let result = request_from_reader(reader)
         .await
         .expect("Failed to parse request");
 
-    assert_eq!(result.body, b"");
+    assert_eq!(HttpVersion::Http10, result.request_line.http_version);
+    assert_eq!("/coffee", result.request_line.request_target);
+}
+
+#[tokio::test]
+async fn unsupported_major_version() {
+    for version in ["HTTP/2.0", "HTTP/0.9", "HTTP/3.0"] {
+        let req_bytes = format!("GET / {}\r\n\r\n", version);
+        let result = request_from_reader(req_bytes.as_bytes()).await;
+
+        assert!(matches!(
+            result,
+            Err(ParseError::UnsupportedVersion { offset: 6 })
+        ));
+    }
+}
+
+#[tokio::test]
+async fn malformed_http_version() {
+    for version in ["HTTX/1.1", "HTTP/1", "HTTP/1.1.1", "http/1.1"] {
+        let req_bytes = format!("GET / {}\r\n\r\n", version);
+        let result = request_from_reader(req_bytes.as_bytes()).await;
+
+        assert!(matches!(
+            result,
+            Err(ParseError::MalformedVersion { offset: 6 })
+        ));
+    }
+}
+
+#[tokio::test]
+async fn request_target_is_parsed() {
+    let req_bytes = b"GET /search?q=hello%20world HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let result = request_from_reader(&req_bytes[..])
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(
+        "/search?q=hello%20world",
+        result.request_line.request_target
+    );
+    assert_eq!("/search", result.request_line.uri.path);
+    assert_eq!(Some("hello world"), result.request_line.uri.query.get("q"));
+}
+
+#[tokio::test]
+async fn request_target_form_must_match_method() {
+    for req_bytes in [
+        &b"CONNECT example.com:443 HTTP/1.1\r\nHost: localhost\r\n\r\n"[..],
+        &b"OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n"[..],
+        &b"GET http://example.com/ HTTP/1.1\r\nHost: localhost\r\n\r\n"[..],
+    ] {
+        assert!(request_from_reader(req_bytes).await.is_ok());
+    }
+
+    for req_bytes in [
+        &b"GET example.com:443 HTTP/1.1\r\n\r\n"[..],
+        &b"CONNECT /tunnel HTTP/1.1\r\n\r\n"[..],
+        &b"GET * HTTP/1.1\r\n\r\n"[..],
+    ] {
+        assert!(request_from_reader(req_bytes).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn malformed_percent_encoding_in_target() {
+    let req_bytes = b"GET /bad%zz HTTP/1.1\r\n\r\n";
+    let result = request_from_reader(&req_bytes[..]).await;
+
+    assert!(matches!(
+        result,
+        Err(ParseError::MalformedTarget { offset: 4 })
+    ));
+}
+
+#[tokio::test]
+async fn request_larger_than_initial_buffer() {
+    let target = format!("/{}", "a".repeat(3000));
+    let req_bytes = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost:42069\r\nCookie: {}\r\n\r\n",
+        target,
+        "b".repeat(5000)
+    );
+    let reader = ChunkReader {
+        data: req_bytes.into_bytes(),
+        num_bytes_per_read: 700,
+        pos: 0,
+    };
+
+    let result = request_from_reader(reader)
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(result.request_line.request_target, target);
+    assert_eq!(result.headers.get("cookie").map(|c| c.len()), Some(5000));
+}
+
+#[tokio::test]
+async fn request_line_too_long() {
+    let limits = Limits {
+        max_request_line: 64,
+        ..Limits::default()
+    };
+
+    let req_bytes = format!(
+        "GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "a".repeat(100)
+    );
+    let reader = ChunkReader {
+        data: req_bytes.into_bytes(),
+        num_bytes_per_read: 16,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::RequestLineTooLong)));
+
+    // Never ending request line, caught before the CRLF shows up.
+    let reader = ChunkReader {
+        data: format!("GET /{}", "a".repeat(1000)).into_bytes(),
+        num_bytes_per_read: 16,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::RequestLineTooLong)));
+}
+
+#[tokio::test]
+async fn header_section_too_large() {
+    let limits = Limits {
+        max_header_size: 128,
+        ..Limits::default()
+    };
+
+    let req_bytes = format!(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n",
+        "a".repeat(200)
+    );
+    let reader = ChunkReader {
+        data: req_bytes.into_bytes(),
+        num_bytes_per_read: 16,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::HeaderSectionTooLarge)));
+
+    let req_bytes = b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 16,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(result.is_ok());
+}
+
+#[tokio::test]
+async fn too_many_headers() {
+    let limits = Limits {
+        max_header_count: 2,
+        ..Limits::default()
+    };
+
+    let req_bytes = b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(result.is_ok());
+
+    let req_bytes =
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\nUser-Agent: curl/7.81.0\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::TooManyHeaders)));
+}
+
+#[tokio::test]
+async fn body_too_large() {
+    let limits = Limits {
+        max_body_size: 8,
+        ..Limits::default()
+    };
+
+    // Rejected on the declared length, before any of the body is read.
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000000\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::BodyTooLarge)));
+
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nHello, \r\n6\r\nworld!\r\n0\r\n\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+    let result = RequestReader::with_limits(reader, limits)
+        .next_request()
+        .await;
+    assert!(matches!(result, Err(ParseError::BodyTooLarge)));
+}
+
+#[tokio::test]
+async fn empty_connection_is_a_clean_close() {
+    let reader = ChunkReader {
+        data: Vec::new(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+
+    let result = RequestReader::new(reader).next_request().await;
+
+    assert!(matches!(result, Ok(None)));
+}
+
+#[tokio::test]
+async fn connection_closed_mid_request() {
+    let requests: [&[u8]; 3] = [
+        b"GET / HT",
+        b"GET / HTTP/1.1\r\nHost: localhost\r\n",
+        b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nHello",
+    ];
+
+    for req_bytes in requests {
+        let reader = ChunkReader {
+            data: req_bytes.to_vec(),
+            num_bytes_per_read: 3,
+            pos: 0,
+        };
+
+        let result = RequestReader::new(reader).next_request().await;
+
+        assert!(matches!(
+            result,
+            Err(ParseError::ConnectionClosed { offset }) if offset == req_bytes.len()
+        ));
+    }
+}
+
+#[tokio::test]
+async fn idle_timeout_is_a_clean_close() {
+    let (_client, server) = tokio::io::duplex(64);
+    let mut reader = RequestReader::new(server);
+    reader.set_timeouts(Timeouts {
+        idle: Some(Duration::from_millis(50)),
+        ..Timeouts::default()
+    });
+
+    let result = reader.next_request().await;
+
+    assert!(matches!(result, Ok(None)));
+}
+
+#[tokio::test]
+async fn header_and_body_timeouts() {
+    let timeouts = Timeouts {
+        idle: Some(Duration::from_secs(10)),
+        header: Some(Duration::from_millis(50)),
+        body: Some(Duration::from_millis(50)),
+    };
+    let requests: [&[u8]; 2] = [
+        b"GET / HTTP/1.1\r\nHost: local",
+        b"POST /submit HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nHello",
+    ];
+
+    for req_bytes in requests {
+        let (mut client, server) = tokio::io::duplex(1024);
+        client.write_all(req_bytes).await.unwrap();
+        let mut reader = RequestReader::new(server);
+        reader.set_timeouts(timeouts);
+
+        let result = reader.next_request().await;
+
+        assert!(matches!(result, Err(ParseError::TimedOut)));
+    }
+}
+
+#[tokio::test]
+async fn errors_point_at_the_failing_component() {
+    let result = request_from_reader(&b"FE(TCH) / HTTP/1.1\r\n\r\n"[..]).await;
+    assert!(matches!(
+        result,
+        Err(ParseError::InvalidMethod { offset: 0 })
+    ));
+
+    let req_bytes = b"GET / HTTP/1.1\r\nHost: localhost\r\nBad Header: value\r\n\r\n";
+    let result = request_from_reader(&req_bytes[..]).await;
+    assert!(matches!(
+        result,
+        Err(ParseError::MalformedHeader { offset: 33 })
+    ));
+
+    // Offsets carry across reads.
+    let req_bytes =
+        b"POST /submit HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\nzz\r\n";
+    let reader = ChunkReader {
+        data: req_bytes.to_vec(),
+        num_bytes_per_read: 3,
+        pos: 0,
+    };
+    let result = request_from_reader(reader).await;
+    assert!(matches!(
+        result,
+        Err(ParseError::MalformedChunk { offset: 80 })
+    ));
+}
+
+#[test]
+fn parse_errors_map_to_status_codes() {
+    let cases = [
+        (
+            ParseError::MalformedRequestLine { offset: 0 },
+            Some(StatusCode::BadRequest),
+        ),
+        (
+            ParseError::InvalidMethod { offset: 0 },
+            Some(StatusCode::BadRequest),
+        ),
+        (
+            ParseError::UnsupportedVersion { offset: 6 },
+            Some(StatusCode::HttpVersionNotSupported),
+        ),
+        (
+            ParseError::ConflictingFraming { offset: 40 },
+            Some(StatusCode::BadRequest),
+        ),
+        (
+            ParseError::MalformedHost { offset: 40 },
+            Some(StatusCode::BadRequest),
+        ),
+        (
+            ParseError::UnsupportedTransferEncoding { offset: 40 },
+            Some(StatusCode::NotImplemented),
+        ),
+        (ParseError::RequestLineTooLong, Some(StatusCode::UriTooLong)),
+        (
+            ParseError::TooManyHeaders,
+            Some(StatusCode::RequestHeaderFieldsTooLarge),
+        ),
+        (ParseError::BodyTooLarge, Some(StatusCode::ContentTooLarge)),
+        (ParseError::TimedOut, Some(StatusCode::RequestTimeout)),
+        (ParseError::ConnectionClosed { offset: 10 }, None),
+    ];
+
+    for (error, status_code) in cases {
+        assert_eq!(error.status_code(), status_code);
+    }
+}
+
+#[tokio::test]
+async fn binary_body_and_obs_text_in_one_read() {
+    let mut req_bytes =
+        b"POST /upload HTTP/1.1\r\nHost: localhost\r\nX-Filename: r\xe9sum\xe9.bin\r\nContent-Length: 4\r\n\r\n"
+            .to_vec();
+    req_bytes.extend_from_slice(&[0xff, 0x00, 0xfe, 0x80]);
+
+    let mut result = request_from_reader(&req_bytes[..])
+        .await
+        .expect("Failed to parse request");
+
+    assert_eq!(
+        result.headers.get_bytes("x-filename"),
+        Some(&b"r\xe9sum\xe9.bin"[..])
+    );
+    assert_eq!(
+        result.body.collect().await.unwrap(),
+        [0xff, 0x00, 0xfe, 0x80]
+    );
+}
+
+// Known request smuggling vectors, each of which some parser in front of us
+// could frame differently. All of them have to be refused outright, whether
+// they arrive at once or a byte at a time.
+#[tokio::test]
+async fn smuggling_vectors_are_rejected() {
+    let cases: [(&[u8], StatusCode); 26] = [
+        // CL.TE and TE.CL
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nG",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n8\r\nSMUGGLED\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        // Conflicting or odd lengths
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 6\r\n\r\nHello!",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 6\r\n\r\nHello!",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: +5\r\n\r\nHello",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0x5\r\n\r\nHello",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5 5\r\n\r\nHello",
+            StatusCode::BadRequest,
+        ),
+        // Obfuscated Transfer-Encoding
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding : chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\n Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding:\r\n chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: xchunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked, identity\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: \"chunked\"\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: \x0bchunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.0\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::NotImplemented,
+        ),
+        // Line endings and control characters inside fields
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nX-Foo: bar\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nX-Foo: bar\rContent-Length: 5\r\n\r\nHello",
+            StatusCode::BadRequest,
+        ),
+        // Missing or repeated Host
+        (
+            b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (b"GET / HTTP/1.1\r\n\r\n", StatusCode::BadRequest),
+        // Chunk framing
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5;a\nXX\r\nHello\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5;a b\r\nHello\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5;=x\r\nHello\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n5;a=\"x\r\nHello\r\n0\r\n\r\n",
+            StatusCode::BadRequest,
+        ),
+    ];
+
+    for (req_bytes, status_code) in cases {
+        for num_bytes_per_read in [1, req_bytes.len()] {
+            let reader = ChunkReader {
+                data: req_bytes.to_vec(),
+                num_bytes_per_read,
+                pos: 0,
+            };
+
+            let result = RequestReader::new(reader).next_request().await;
+
+            match result {
+                Err(e) => assert_eq!(
+                    e.status_code().as_ref(),
+                    Some(&status_code),
+                    "{}: {}",
+                    String::from_utf8_lossy(req_bytes),
+                    e
+                ),
+                Ok(_) => panic!("accepted {}", String::from_utf8_lossy(req_bytes)),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn unambiguous_framing_is_accepted() {
+    let cases: [(&[u8], &[u8]); 4] = [
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nHello",
+            b"Hello",
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5, 5\r\n\r\nHello",
+            b"Hello",
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: CHUNKED\r\n\r\n5\r\nHello\r\n0\r\n\r\n",
+            b"Hello",
+        ),
+        (
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\n\r\n",
+            b"",
+        ),
+    ];
+
+    for (req_bytes, body) in cases {
+        let reader = ChunkReader {
+            data: req_bytes.to_vec(),
+            num_bytes_per_read: 1,
+            pos: 0,
+        };
+
+        let mut result = RequestReader::new(reader)
+            .next_request()
+            .await
+            .expect("Failed to parse request")
+            .expect("Connection closed");
+
+        assert_eq!(result.body.collect().await.unwrap(), body);
+    }
+}
+
+#[tokio::test]
+async fn body_reads_as_a_stream() {
+    let req_bytes = b"POST /submit HTTP/1.1\r\nHost: localhost:42069\r\nTransfer-Encoding: chunked\r\n\r\n7\r\nHello, \r\n6\r\nworld!\r\n0\r\n\r\n";
+    let mut result = request_from_reader(&req_bytes[..])
+        .await
+        .expect("Failed to parse request");
+
+    let mut start = [0u8; 4];
+    result.body.read_exact(&mut start).await.unwrap();
+    let mut rest = Vec::new();
+    result.body.read_to_end(&mut rest).await.unwrap();
+
+    assert_eq!(&start, b"Hell");
+    assert_eq!(rest, b"o, world!");
+    assert_eq!(result.body.chunk().await.unwrap(), None);
 }
@@ -3,37 +3,45 @@ use std::io::Error;
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::headers::Headers;
+use crate::request::HttpVersion;
+use crate::server::HandlerError;
 
-#[repr(u16)]
-pub enum StatusCode {
-    Ok = 200,
-    BadRequest = 400,
-    InternalServerError = 500,
-}
+mod status;
+mod writer;
+
+pub use status::StatusCode;
+pub use writer::ResponseWriter;
+pub(crate) use writer::{Continue, Stream};
 
-pub async fn write_status_line<W>(stream: &mut W, status_code: StatusCode) -> Result<(), Error>
+pub async fn write_status_line<W>(
+    stream: &mut W,
+    version: HttpVersion,
+    status_code: &StatusCode,
+) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin,
 {
-    let reason = match status_code {
-        StatusCode::Ok => "Ok",
-        StatusCode::BadRequest => "Bad Request",
-        StatusCode::InternalServerError => "Internal Server Error",
-    };
+    let code = status_code.code();
+    let reason = status_code.reason();
+    if !(100..=999).contains(&code) || reason.contains(['\r', '\n']) {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "Invalid Status Code",
+        ));
+    }
 
     stream
-        .write_all(format!("HTTP/1.1 {} {}\r\n", status_code as u16, reason).as_bytes())
+        .write_all(format!("{} {} {}\r\n", version.as_str(), code, reason).as_bytes())
         .await?;
 
     Ok(())
 }
 
-pub fn get_default_headers(content_len: u16) -> Headers {
+pub fn get_default_headers(content_len: u64) -> Headers {
     let mut headers = Headers::new();
 
-    headers.set("Content-length", &content_len.to_string());
-    headers.set("Connection", "close");
-    headers.set("Content-Type", "text/plain");
+    headers.append("Content-Length", content_len.to_string());
+    headers.append("Content-Type", "text/plain");
 
     headers
 }
@@ -42,13 +50,28 @@ pub async fn write_headers<W>(stream: &mut W, headers: Headers) -> Result<(), Er
 where
     W: AsyncWrite + Unpin,
 {
-    for (key, value) in headers.headers {
-        stream
-            .write_all(format!("{}: {}\r\n", key, value).as_bytes())
-            .await?;
-    }
+    let mut buf = Vec::new();
+    headers.serialize(&mut buf)?;
 
-    stream.write_all(b"\r\n").await?;
+    stream.write_all(&buf).await?;
 
     Ok(())
 }
+
+// Answers with the status and its reason phrase, for a handler turning a
+// request away.
+pub(crate) async fn reject(
+    mut writer: ResponseWriter,
+    status_code: StatusCode,
+) -> Option<HandlerError> {
+    let message = status_code.reason().to_string();
+    writer.set_status(status_code);
+
+    if let Err(e) = writer.write_all(message.as_bytes()).await {
+        eprintln!("Failed to write body: {}", e);
+    }
+    None
+}
+
+#[cfg(test)]
+mod test;
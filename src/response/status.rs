@@ -0,0 +1,122 @@
+macro_rules! status_codes {
+    ($(($variant:ident, $code:literal, $reason:literal),)+) => {
+        /// Status codes from the IANA HTTP Status Code Registry. Anything else
+        /// can be sent with [`StatusCode::Custom`].
+        #[derive(Clone, Debug, PartialEq)]
+        pub enum StatusCode {
+            $($variant,)+
+            Custom(u16, String),
+        }
+
+        impl StatusCode {
+            pub fn code(&self) -> u16 {
+                match self {
+                    $(StatusCode::$variant => $code,)+
+                    StatusCode::Custom(code, _) => *code,
+                }
+            }
+
+            pub fn reason(&self) -> &str {
+                match self {
+                    $(StatusCode::$variant => $reason,)+
+                    StatusCode::Custom(_, reason) => reason,
+                }
+            }
+
+            /// Looks up a registered code, falling back to a `Custom` code with
+            /// an empty reason phrase.
+            pub fn from_u16(code: u16) -> StatusCode {
+                match code {
+                    $($code => StatusCode::$variant,)+
+                    _ => StatusCode::Custom(code, String::new()),
+                }
+            }
+        }
+    };
+}
+
+status_codes! {
+    (Continue, 100, "Continue"),
+    (SwitchingProtocols, 101, "Switching Protocols"),
+    (Processing, 102, "Processing"),
+    (EarlyHints, 103, "Early Hints"),
+    (Ok, 200, "OK"),
+    (Created, 201, "Created"),
+    (Accepted, 202, "Accepted"),
+    (NonAuthoritativeInformation, 203, "Non-Authoritative Information"),
+    (NoContent, 204, "No Content"),
+    (ResetContent, 205, "Reset Content"),
+    (PartialContent, 206, "Partial Content"),
+    (MultiStatus, 207, "Multi-Status"),
+    (AlreadyReported, 208, "Already Reported"),
+    (ImUsed, 226, "IM Used"),
+    (MultipleChoices, 300, "Multiple Choices"),
+    (MovedPermanently, 301, "Moved Permanently"),
+    (Found, 302, "Found"),
+    (SeeOther, 303, "See Other"),
+    (NotModified, 304, "Not Modified"),
+    (UseProxy, 305, "Use Proxy"),
+    (TemporaryRedirect, 307, "Temporary Redirect"),
+    (PermanentRedirect, 308, "Permanent Redirect"),
+    (BadRequest, 400, "Bad Request"),
+    (Unauthorized, 401, "Unauthorized"),
+    (PaymentRequired, 402, "Payment Required"),
+    (Forbidden, 403, "Forbidden"),
+    (NotFound, 404, "Not Found"),
+    (MethodNotAllowed, 405, "Method Not Allowed"),
+    (NotAcceptable, 406, "Not Acceptable"),
+    (ProxyAuthenticationRequired, 407, "Proxy Authentication Required"),
+    (RequestTimeout, 408, "Request Timeout"),
+    (Conflict, 409, "Conflict"),
+    (Gone, 410, "Gone"),
+    (LengthRequired, 411, "Length Required"),
+    (PreconditionFailed, 412, "Precondition Failed"),
+    (ContentTooLarge, 413, "Content Too Large"),
+    (UriTooLong, 414, "URI Too Long"),
+    (UnsupportedMediaType, 415, "Unsupported Media Type"),
+    (RangeNotSatisfiable, 416, "Range Not Satisfiable"),
+    (ExpectationFailed, 417, "Expectation Failed"),
+    (MisdirectedRequest, 421, "Misdirected Request"),
+    (UnprocessableContent, 422, "Unprocessable Content"),
+    (Locked, 423, "Locked"),
+    (FailedDependency, 424, "Failed Dependency"),
+    (TooEarly, 425, "Too Early"),
+    (UpgradeRequired, 426, "Upgrade Required"),
+    (PreconditionRequired, 428, "Precondition Required"),
+    (TooManyRequests, 429, "Too Many Requests"),
+    (RequestHeaderFieldsTooLarge, 431, "Request Header Fields Too Large"),
+    (UnavailableForLegalReasons, 451, "Unavailable For Legal Reasons"),
+    (InternalServerError, 500, "Internal Server Error"),
+    (NotImplemented, 501, "Not Implemented"),
+    (BadGateway, 502, "Bad Gateway"),
+    (ServiceUnavailable, 503, "Service Unavailable"),
+    (GatewayTimeout, 504, "Gateway Timeout"),
+    (HttpVersionNotSupported, 505, "HTTP Version Not Supported"),
+    (VariantAlsoNegotiates, 506, "Variant Also Negotiates"),
+    (InsufficientStorage, 507, "Insufficient Storage"),
+    (LoopDetected, 508, "Loop Detected"),
+    (NotExtended, 510, "Not Extended"),
+    (NetworkAuthenticationRequired, 511, "Network Authentication Required"),
+}
+
+impl StatusCode {
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    pub fn is_redirection(&self) -> bool {
+        (300..400).contains(&self.code())
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.code())
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.code())
+    }
+}
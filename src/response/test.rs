@@ -0,0 +1,449 @@
+use super::*;
+
+use crate::request::HttpVersion;
+
+use std::io::ErrorKind;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+async fn read_all(mut client: DuplexStream) -> String {
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).await.unwrap();
+    String::from_utf8(buf).unwrap()
+}
+
+#[tokio::test]
+async fn buffered_response() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, false, false);
+
+    writer.write_all(b"Hello, ").await.unwrap();
+    writer.write_all(b"world!").await.unwrap();
+    drop(writer);
+
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 13\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(output.ends_with("\r\n\r\nHello, world!"));
+}
+
+#[tokio::test]
+async fn chunked_response_with_trailers() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.write_all(b"Hello, ").await.unwrap();
+    writer.start_chunked().await.unwrap();
+    writer.write_all(b"world!").await.unwrap();
+    writer.set_trailer("X-Content-Sha256", "abc123").unwrap();
+    drop(writer);
+
+    assert!(response.is_streaming());
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(!output.contains("Content-Length"));
+    assert!(!output.contains("Connection"));
+    assert!(
+        output.ends_with(
+            "\r\n\r\n7\r\nHello, \r\n6\r\nworld!\r\n0\r\nX-Content-Sha256: abc123\r\n\r\n"
+        )
+    );
+}
+
+#[tokio::test]
+async fn chunked_head_is_sent_before_handler_returns() {
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, _response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.start_chunked().await.unwrap();
+    writer.write_all(b"tick").await.unwrap();
+    writer.flush().await.unwrap();
+
+    let mut buf = vec![0u8; 1024];
+    let n = client.read(&mut buf).await.unwrap();
+    let output = String::from_utf8_lossy(&buf[..n]);
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.ends_with("\r\n\r\n4\r\ntick\r\n"));
+}
+
+#[tokio::test]
+async fn handler_status_and_headers() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.set_status(StatusCode::BadRequest);
+    writer
+        .set_header("Content-Type", "application/json")
+        .unwrap();
+    writer.set_header("Cache-Control", "no-store").unwrap();
+    writer.set_header("Content-Length", "9999").unwrap();
+    writer.write_all(b"{}").await.unwrap();
+    drop(writer);
+
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.contains("Content-Type: application/json\r\n"));
+    assert!(!output.contains("text/plain"));
+    assert!(output.contains("Cache-Control: no-store\r\n"));
+    assert!(output.contains("Content-Length: 2\r\n"));
+    assert!(output.ends_with("\r\n\r\n{}"));
+}
+
+#[tokio::test]
+async fn invalid_header_fields_are_refused() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    let splitting = "/x\r\nSet-Cookie: evil=1";
+    let err = writer.set_header("Location", splitting).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    assert!(writer.append_header("Location", "/x\n").is_err());
+    assert!(writer.set_header("Bad Name", "1").is_err());
+    assert!(writer.set_header("", "1").is_err());
+    assert!(writer.set_trailer("X-Sum", "a\r\n\r\nb").is_err());
+    writer.set_header("X-Folded", "caf\u{e9}\tok").unwrap();
+    drop(writer);
+
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(!output.contains("Location"));
+    assert!(!output.contains("evil"));
+    assert!(output.contains("X-Folded: caf\u{e9}\tok\r\n"));
+}
+
+#[tokio::test]
+async fn repeated_headers_are_sent_separately() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.append_header("Set-Cookie", "a=1; Path=/").unwrap();
+    writer.append_header("Set-Cookie", "b=2, c").unwrap();
+    drop(writer);
+
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(output.contains("\r\nSet-Cookie: a=1; Path=/\r\nSet-Cookie: b=2, c\r\n"));
+}
+
+#[tokio::test]
+async fn failed_response_drops_handler_headers() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.set_header("Location", "/elsewhere").unwrap();
+    writer.write_all(b"partial").await.unwrap();
+    drop(writer);
+
+    response.fail(StatusCode::InternalServerError, "oops");
+    let stream = response.finish().await.unwrap();
+    drop(stream);
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+    assert!(!output.contains("Location"));
+    assert!(output.ends_with("\r\n\r\noops"));
+}
+
+#[tokio::test]
+async fn standard_status_lines() {
+    let mut line = Vec::new();
+    write_status_line(&mut line, HttpVersion::Http11, &StatusCode::NotFound)
+        .await
+        .unwrap();
+    assert_eq!(line, b"HTTP/1.1 404 Not Found\r\n");
+
+    let mut line = Vec::new();
+    write_status_line(&mut line, HttpVersion::Http11, &StatusCode::TooManyRequests)
+        .await
+        .unwrap();
+    assert_eq!(line, b"HTTP/1.1 429 Too Many Requests\r\n");
+}
+
+#[tokio::test]
+async fn custom_status_line() {
+    let mut line = Vec::new();
+    write_status_line(
+        &mut line,
+        HttpVersion::Http11,
+        &StatusCode::Custom(599, "Network Hiccup".to_string()),
+    )
+    .await
+    .unwrap();
+    assert_eq!(line, b"HTTP/1.1 599 Network Hiccup\r\n");
+}
+
+#[tokio::test]
+async fn invalid_custom_status_line() {
+    let mut line = Vec::new();
+    let result = write_status_line(
+        &mut line,
+        HttpVersion::Http11,
+        &StatusCode::Custom(42, "Nope".to_string()),
+    )
+    .await;
+    assert!(result.is_err());
+
+    let result = write_status_line(
+        &mut line,
+        HttpVersion::Http11,
+        &StatusCode::Custom(299, "Bad\r\nInjected: yes".to_string()),
+    )
+    .await;
+    assert!(result.is_err());
+    assert!(line.is_empty());
+}
+
+#[test]
+fn status_code_classes() {
+    assert!(StatusCode::Continue.is_informational());
+    assert!(StatusCode::Created.is_success());
+    assert!(StatusCode::PermanentRedirect.is_redirection());
+    assert!(StatusCode::MethodNotAllowed.is_client_error());
+    assert!(StatusCode::ServiceUnavailable.is_server_error());
+    assert!(!StatusCode::ServiceUnavailable.is_client_error());
+}
+
+#[test]
+fn status_code_from_u16() {
+    assert_eq!(StatusCode::from_u16(413), StatusCode::ContentTooLarge);
+    assert_eq!(StatusCode::from_u16(413).reason(), "Content Too Large");
+    assert_eq!(
+        StatusCode::from_u16(499),
+        StatusCode::Custom(499, String::new())
+    );
+}
+
+#[tokio::test]
+async fn head_response_has_length_but_no_body() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, true);
+
+    writer.write_all(b"Hello, world!").await.unwrap();
+    drop(writer);
+
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.contains("Content-Length: 13\r\n"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn head_response_when_chunked() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, true);
+
+    writer.start_chunked().await.unwrap();
+    writer.write_all(b"Hello, world!").await.unwrap();
+    writer.set_trailer("X-Content-Sha256", "abc123").unwrap();
+    drop(writer);
+
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+    assert!(output.ends_with("\r\n\r\n"));
+    assert!(!output.contains("Hello"));
+    assert!(!output.contains("abc123"));
+}
+
+#[tokio::test]
+async fn http_1_0_keep_alive_response() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http10, true, false);
+
+    writer.write_all(b"Hello").await.unwrap();
+    drop(writer);
+
+    assert!(response.keep_alive());
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(output.contains("Connection: keep-alive\r\n"));
+    assert!(output.contains("Content-Length: 5\r\n"));
+}
+
+#[tokio::test]
+async fn http_1_0_streamed_response_is_close_delimited() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http10, true, false);
+
+    writer.start_chunked().await.unwrap();
+    writer.write_all(b"Hello, ").await.unwrap();
+    writer.write_all(b"world!").await.unwrap();
+    writer.set_trailer("X-Content-Sha256", "abc123").unwrap();
+    drop(writer);
+
+    assert!(!response.keep_alive());
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.0 200 OK\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(!output.contains("Transfer-Encoding"));
+    assert!(!output.contains("Content-Length"));
+    assert!(output.ends_with("\r\n\r\nHello, world!"));
+}
+
+#[tokio::test]
+async fn buffered_response_over_64_kib() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, false, false);
+    let reading = tokio::spawn(read_all(client));
+
+    writer.write_all(&[b'a'; 100_000]).await.unwrap();
+    drop(writer);
+    drop(response.finish().await.unwrap());
+
+    let output = reading.await.unwrap();
+    assert!(output.contains("Content-Length: 100000\r\n"));
+    assert!(output.ends_with(&format!("\r\n\r\n{}", "a".repeat(100_000))));
+}
+
+#[tokio::test]
+async fn declared_length_is_streamed_as_is() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.write_all(b"Hello, ").await.unwrap();
+    writer.start_with_length(13).await.unwrap();
+    writer.write_all(b"world!").await.unwrap();
+    drop(writer);
+
+    assert!(response.is_streaming());
+    assert!(response.keep_alive());
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 13\r\n"));
+    assert!(!output.contains("Transfer-Encoding"));
+    assert!(output.ends_with("\r\n\r\nHello, world!"));
+}
+
+#[tokio::test]
+async fn writes_past_the_declared_length_fail() {
+    let (_client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.start_with_length(5).await.unwrap();
+    writer.write_all(b"Hel").await.unwrap();
+    let err = writer.write_all(b"lo, world!").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    drop(writer);
+
+    let err = response.finish().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn short_declared_length_fails() {
+    let (_client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.start_with_length(13).await.unwrap();
+    writer.write_all(b"Hello").await.unwrap();
+    drop(writer);
+
+    let err = response.finish().await.err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("8 bytes short"));
+}
+
+#[tokio::test]
+async fn head_response_with_declared_length() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, false, true);
+
+    writer.start_with_length(13).await.unwrap();
+    drop(writer);
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.contains("Content-Length: 13\r\n"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn not_modified_has_no_length() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.set_status(StatusCode::NotModified);
+    writer.set_header("ETag", "\"abc\"").unwrap();
+    drop(writer);
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert_eq!(output, "HTTP/1.1 304 Not Modified\r\nETag: \"abc\"\r\n\r\n");
+}
+
+#[tokio::test]
+async fn no_content_when_chunked_has_no_framing() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.set_status(StatusCode::NoContent);
+    writer.write_all(b"dropped").await.unwrap();
+    writer.start_chunked().await.unwrap();
+    writer.write_all(b"dropped too").await.unwrap();
+    drop(writer);
+
+    assert!(response.keep_alive());
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert_eq!(output, "HTTP/1.1 204 No Content\r\n\r\n");
+}
+
+#[tokio::test]
+async fn handler_can_close_the_connection() {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    let (mut writer, response) =
+        ResponseWriter::new(Box::new(server), HttpVersion::Http11, true, false);
+
+    writer.set_header("Connection", "Close").unwrap();
+    writer.write_all(b"bye").await.unwrap();
+    drop(writer);
+
+    assert!(!response.keep_alive());
+    drop(response.finish().await.unwrap());
+
+    let output = read_all(client).await;
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(!output.contains("Connection: Close\r\n"));
+}
@@ -0,0 +1,558 @@
+use std::io::{Error, ErrorKind};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::headers::{self, Headers};
+use crate::request::HttpVersion;
+use crate::response::{self, StatusCode};
+
+pub(crate) type Stream = Box<dyn AsyncWrite + Send + Unpin>;
+
+#[derive(PartialEq)]
+enum Mode {
+    Buffered,
+    Chunked,
+    // The handler declared the length up front, the body goes out as is.
+    Sized { remaining: u64 },
+    // HTTP/1.0 clients don't understand chunks, so a streamed body is ended
+    // by closing the connection instead.
+    CloseDelimited,
+    // The status forbids content, so whatever is written is dropped.
+    Bodiless,
+}
+
+struct State {
+    // Only present while the handler owns the response.
+    stream: Option<Stream>,
+    mode: Mode,
+    version: HttpVersion,
+    keep_alive: bool,
+    // Answering a HEAD request: the head goes out, the body doesn't.
+    head_only: bool,
+    // Whether a body without a Content-Type is labelled text/plain.
+    default_content_type: bool,
+    status_code: StatusCode,
+    headers: Headers,
+    body: Vec<u8>,
+    trailers: Headers,
+    // Encoded bytes that still have to reach the stream.
+    pending: Vec<u8>,
+    // The handler tried to write past the length it declared.
+    overrun: bool,
+}
+
+impl State {
+    fn new(
+        stream: Option<Stream>,
+        version: HttpVersion,
+        keep_alive: bool,
+        head_only: bool,
+    ) -> State {
+        State {
+            stream,
+            mode: Mode::Buffered,
+            version,
+            keep_alive,
+            head_only,
+            default_content_type: true,
+            status_code: StatusCode::Ok,
+            headers: Headers::new(),
+            body: Vec::new(),
+            trailers: Headers::new(),
+            pending: Vec::new(),
+            overrun: false,
+        }
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        while !self.pending.is_empty() {
+            let stream = match self.stream.as_mut() {
+                Some(s) => s,
+                None => return Poll::Ready(Err(Error::from(ErrorKind::BrokenPipe))),
+            };
+            let n = ready!(Pin::new(stream).poll_write(cx, &self.pending))?;
+            if n == 0 {
+                return Poll::Ready(Err(Error::from(ErrorKind::WriteZero)));
+            }
+            self.pending.drain(..n);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    // 1xx, 204 and 304 never have content, nor a length for it.
+    fn is_bodiless(&self) -> bool {
+        matches!(self.status_code.code(), 100..=199 | 204 | 304)
+    }
+
+    // The handler's headers win over the defaults, except for the framing the
+    // server is responsible for.
+    fn response_headers(&self, defaults: Headers) -> Headers {
+        let is_framing = |key: &str| {
+            ["content-length", "transfer-encoding", "connection"]
+                .iter()
+                .any(|name| key.eq_ignore_ascii_case(name))
+        };
+
+        let mut headers = defaults;
+        for (key, _) in self.headers.iter() {
+            if !is_framing(key) {
+                headers.remove(key);
+            }
+        }
+        for (key, value) in self.headers.iter() {
+            if !is_framing(key) {
+                headers.append(key, value);
+            }
+        }
+
+        if !self.keep_alive {
+            headers.insert("Connection", "close");
+        } else if self.version == HttpVersion::Http10 {
+            headers.insert("Connection", "keep-alive");
+        }
+
+        headers
+    }
+
+    fn fail(&mut self, status_code: StatusCode, message: &str, headers: Headers) {
+        self.default_content_type = true;
+        self.status_code = status_code;
+        self.headers = headers;
+        self.body.clear();
+        self.body.extend_from_slice(message.as_bytes());
+    }
+
+    // A buffered body is kept even for HEAD, its length still goes out.
+    fn queue_body(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() || (self.head_only && self.mode != Mode::Buffered) {
+            return Ok(());
+        }
+
+        match &mut self.mode {
+            Mode::Buffered => self.body.extend_from_slice(data),
+            Mode::Chunked => {
+                self.pending
+                    .extend_from_slice(format!("{:x}\r\n", data.len()).as_bytes());
+                self.pending.extend_from_slice(data);
+                self.pending.extend_from_slice(b"\r\n");
+            }
+            Mode::Sized { remaining } => {
+                if data.len() as u64 > *remaining {
+                    self.overrun = true;
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "Response body longer than its Content-Length",
+                    ));
+                }
+                *remaining -= data.len() as u64;
+                self.pending.extend_from_slice(data);
+            }
+            Mode::CloseDelimited => self.pending.extend_from_slice(data),
+            Mode::Bodiless => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn asks_to_close(key: &str, value: &[u8]) -> bool {
+    key.eq_ignore_ascii_case("connection")
+        && value
+            .split(|b| *b == b',')
+            .any(|option| headers::trim_ows(option).eq_ignore_ascii_case(b"close"))
+}
+
+/// Builds a response. The status and headers can be changed until the head is
+/// sent, which is when the handler returns, or right away once the handler
+/// switches to chunked mode with [`ResponseWriter::start_chunked`]. Writes
+/// are buffered and sent with a `Content-Length` unless the response is
+/// chunked.
+///
+/// The response is over when the handler returns. A writer kept around
+/// after that is detached from the connection and every write fails.
+pub struct ResponseWriter {
+    state: Arc<Mutex<State>>,
+}
+
+/// The server's side of a [`ResponseWriter`], used to send whatever the
+/// handler left behind.
+pub(crate) struct PendingResponse {
+    state: Arc<Mutex<State>>,
+}
+
+/// Sends the `100 Continue` interim response a client asked for with
+/// `Expect: 100-continue`, unless the final response has already started.
+pub(crate) struct Continue {
+    state: Arc<Mutex<State>>,
+    queued: bool,
+}
+
+impl Continue {
+    pub(crate) fn poll_send(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        if !self.queued {
+            if state.mode != Mode::Buffered {
+                return Poll::Ready(Ok(()));
+            }
+            state
+                .pending
+                .extend_from_slice(b"HTTP/1.1 100 Continue\r\n\r\n");
+            self.queued = true;
+        }
+
+        ready!(state.poll_pending(cx))?;
+        match state.stream.as_mut() {
+            Some(stream) => Pin::new(stream).poll_flush(cx),
+            None => Poll::Ready(Err(Error::from(ErrorKind::BrokenPipe))),
+        }
+    }
+}
+
+impl ResponseWriter {
+    pub(crate) fn new(
+        stream: Stream,
+        version: HttpVersion,
+        keep_alive: bool,
+        head_only: bool,
+    ) -> (ResponseWriter, PendingResponse) {
+        let state = Arc::new(Mutex::new(State::new(
+            Some(stream),
+            version,
+            keep_alive,
+            head_only,
+        )));
+
+        (
+            ResponseWriter {
+                state: Arc::clone(&state),
+            },
+            PendingResponse { state },
+        )
+    }
+
+    /// Sends the status line and headers right away. Every write after this
+    /// goes out as its own chunk, and the terminating chunk and trailers are
+    /// sent when the handler returns.
+    ///
+    /// HTTP/1.0 clients get the body unframed instead, followed by the
+    /// connection closing, and no trailers.
+    ///
+    /// A status that can't have content, such as 204 or 304, gets neither
+    /// chunks nor a length, and every write is dropped.
+    pub async fn start_chunked(&mut self) -> Result<(), Error> {
+        self.start(Mode::Chunked).await
+    }
+
+    /// Sends the status line and headers right away, with a `Content-Length`
+    /// of `content_length`. Every write after this goes straight out.
+    ///
+    /// The body has to be exactly that long: a write that would run past it
+    /// fails, and a response cut short or overrun when the handler returns
+    /// closes the connection.
+    pub async fn start_with_length(&mut self, content_length: u64) -> Result<(), Error> {
+        self.start(Mode::Sized {
+            remaining: content_length,
+        })
+        .await
+    }
+
+    async fn start(&mut self, mode: Mode) -> Result<(), Error> {
+        let (version, status_code, headers, body) = {
+            let mut state = self.state.lock().unwrap();
+            if state.mode != Mode::Buffered {
+                return Ok(());
+            }
+
+            let mut defaults = Headers::new();
+            if state.default_content_type && !state.is_bodiless() {
+                defaults.append("Content-Type", "text/plain");
+            }
+            match mode {
+                _ if state.is_bodiless() => state.mode = Mode::Bodiless,
+                Mode::Sized { remaining } => {
+                    defaults.append("Content-Length", remaining.to_string());
+                    state.mode = mode;
+                }
+                _ if state.version == HttpVersion::Http10 => {
+                    state.mode = Mode::CloseDelimited;
+                    state.keep_alive = false;
+                }
+                _ => {
+                    state.mode = Mode::Chunked;
+                    defaults.append("Transfer-Encoding", "chunked");
+                }
+            }
+
+            (
+                state.version,
+                state.status_code.clone(),
+                state.response_headers(defaults),
+                std::mem::take(&mut state.body),
+            )
+        };
+
+        let mut head = Vec::new();
+        response::write_status_line(&mut head, version, &status_code).await?;
+        response::write_headers(&mut head, headers).await?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.pending.extend_from_slice(&head);
+            state.queue_body(&body)?;
+        }
+
+        self.flush().await
+    }
+
+    pub fn set_status(&mut self, status_code: StatusCode) {
+        self.state.lock().unwrap().status_code = status_code;
+    }
+
+    /// Sets a header, replacing any earlier value. `Content-Length`,
+    /// `Transfer-Encoding` and `Connection` are managed by the server and
+    /// not sent as set, though a `Connection` with the `close` option does
+    /// close the connection after this response.
+    ///
+    /// The name has to be a token and the value can't hold control
+    /// characters such as CR or LF, anything else is refused. Bytes past
+    /// ASCII are sent as they are.
+    pub fn set_header(&mut self, key: &str, value: impl AsRef<[u8]>) -> Result<(), Error> {
+        headers::check_field(key, value.as_ref())?;
+        let mut state = self.state.lock().unwrap();
+        if asks_to_close(key, value.as_ref()) {
+            state.keep_alive = false;
+        }
+        state.headers.insert(key, value);
+        Ok(())
+    }
+
+    /// Adds a header, keeping any earlier ones with the same name, e.g. for
+    /// several `Set-Cookie` fields. Checked like [`set_header`](Self::set_header).
+    pub fn append_header(&mut self, key: &str, value: impl AsRef<[u8]>) -> Result<(), Error> {
+        headers::check_field(key, value.as_ref())?;
+        let mut state = self.state.lock().unwrap();
+        if asks_to_close(key, value.as_ref()) {
+            state.keep_alive = false;
+        }
+        state.headers.append(key, value);
+        Ok(())
+    }
+
+    pub fn remove_header(&mut self, key: &str) {
+        self.state.lock().unwrap().headers.remove(key);
+    }
+
+    /// Adds a trailer field, sent after the last chunk. Ignored unless the
+    /// response is chunked. Checked like [`set_header`](Self::set_header).
+    pub fn set_trailer(&mut self, key: &str, value: impl AsRef<[u8]>) -> Result<(), Error> {
+        headers::check_field(key, value.as_ref())?;
+        self.state.lock().unwrap().trailers.append(key, value);
+        Ok(())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.state.lock().unwrap().status_code.clone()
+    }
+
+    /// The headers set so far, without the ones the server adds.
+    pub fn headers(&self) -> Headers {
+        self.state.lock().unwrap().headers.clone()
+    }
+
+    /// Whether this answers a `HEAD` request. Only the head goes out, so a streamed body needn't be produced at
+    /// all: [`start_with_length`](Self::start_with_length) still sends its
+    /// length, and nothing written after it is sent.
+    pub fn is_head(&self) -> bool {
+        self.state.lock().unwrap().head_only
+    }
+
+    /// Whether the status line and headers are already out, after which
+    /// changing them does nothing.
+    pub fn is_started(&self) -> bool {
+        self.state.lock().unwrap().mode != Mode::Buffered
+    }
+
+    /// Takes the body written so far, to be replaced by the next writes.
+    /// `None` once the response has started, as the body is already out.
+    pub fn take_body(&mut self) -> Option<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        if state.mode != Mode::Buffered {
+            return None;
+        }
+        Some(std::mem::take(&mut state.body))
+    }
+
+    // Sends no Content-Type unless one is set, for a response passed on
+    // from elsewhere that came without one.
+    pub(crate) fn no_default_content_type(&mut self) {
+        self.state.lock().unwrap().default_content_type = false;
+    }
+
+    // Replaces a buffered response with an error response, with `headers`
+    // in place of whatever was set.
+    pub(crate) fn fail(&mut self, status_code: StatusCode, message: &str, headers: Headers) {
+        self.state
+            .lock()
+            .unwrap()
+            .fail(status_code, message, headers);
+    }
+
+    // Another handle on the same response, for a handler called on behalf
+    // of this one.
+    pub(crate) fn share(&self) -> ResponseWriter {
+        ResponseWriter {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+impl AsyncWrite for ResponseWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.stream.is_none() {
+            return Poll::Ready(Err(Error::from(ErrorKind::BrokenPipe)));
+        }
+        if state.mode == Mode::Buffered {
+            state.queue_body(buf)?;
+            return Poll::Ready(Ok(buf.len()));
+        }
+
+        // Keep at most one write queued so a slow client pushes back on the
+        // handler.
+        ready!(state.poll_pending(cx))?;
+        state.queue_body(buf)?;
+        if let Poll::Ready(Err(e)) = state.poll_pending(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.mode == Mode::Buffered {
+            return Poll::Ready(Ok(()));
+        }
+
+        ready!(state.poll_pending(cx))?;
+        match state.stream.as_mut() {
+            Some(stream) => Pin::new(stream).poll_flush(cx),
+            None => Poll::Ready(Err(Error::from(ErrorKind::BrokenPipe))),
+        }
+    }
+
+    // The connection outlives the response, so shutting the writer down only
+    // flushes it.
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl PendingResponse {
+    /// Whether the status line and headers are already out.
+    pub(crate) fn is_streaming(&self) -> bool {
+        self.state.lock().unwrap().mode != Mode::Buffered
+    }
+
+    /// Whether the connection can be reused once the response is finished.
+    pub(crate) fn keep_alive(&self) -> bool {
+        self.state.lock().unwrap().keep_alive
+    }
+
+    pub(crate) fn interim(&self) -> Continue {
+        Continue {
+            state: Arc::clone(&self.state),
+            queued: false,
+        }
+    }
+
+    /// Closes the connection after this response, announcing it if the head
+    /// hasn't gone out yet.
+    pub(crate) fn close_connection(&self) {
+        self.state.lock().unwrap().keep_alive = false;
+    }
+
+    /// Replaces a buffered response with an error response.
+    pub(crate) fn fail(&self, status_code: StatusCode, message: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .fail(status_code, message, Headers::new());
+    }
+
+    /// Sends the rest of the response and hands the stream back.
+    pub(crate) async fn finish(self) -> Result<Stream, Error> {
+        let (mut stream, state) = {
+            let mut guard = self.state.lock().unwrap();
+            let stream = match guard.stream.take() {
+                Some(s) => s,
+                None => return Err(Error::from(ErrorKind::BrokenPipe)),
+            };
+            let detached = State::new(None, guard.version, guard.keep_alive, guard.head_only);
+            (stream, std::mem::replace(&mut *guard, detached))
+        };
+
+        stream.write_all(&state.pending).await?;
+        match state.mode {
+            Mode::Buffered => {
+                let bodiless = state.is_bodiless();
+                let mut defaults = if bodiless {
+                    Headers::new()
+                } else {
+                    response::get_default_headers(state.body.len() as u64)
+                };
+                if !state.default_content_type {
+                    defaults.remove("content-type");
+                }
+                let headers = state.response_headers(defaults);
+                response::write_status_line(&mut stream, state.version, &state.status_code).await?;
+                response::write_headers(&mut stream, headers).await?;
+                if !state.head_only && !bodiless {
+                    stream.write_all(&state.body).await?;
+                }
+            }
+            Mode::Chunked if !state.head_only => {
+                stream.write_all(b"0\r\n").await?;
+                response::write_headers(&mut stream, state.trailers).await?;
+            }
+            // The head promised a length, the connection can't carry on
+            // after anything else.
+            Mode::Sized { .. } if !state.head_only && state.overrun => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Handler wrote past the declared Content-Length",
+                ));
+            }
+            Mode::Sized { remaining } if !state.head_only && remaining > 0 => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Response body {} bytes short of the declared Content-Length",
+                        remaining
+                    ),
+                ));
+            }
+            _ => {}
+        }
+        stream.flush().await?;
+
+        Ok(stream)
+    }
+
+    /// Takes the stream back without finishing the response, e.g. after a
+    /// streaming handler failed and the connection has to be dropped.
+    pub(crate) fn abort(self) -> Option<Stream> {
+        self.state.lock().unwrap().stream.take()
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+
+use crate::request::{Request, RequestMethod};
+use crate::response::{self, StatusCode};
+use crate::server::{Handler, HandlerError, Writer};
+
+enum Segment {
+    Static(String),
+    Param(String),
+    Wildcard(String),
+}
+
+struct Route {
+    method: RequestMethod,
+    pattern: Vec<Segment>,
+    handler: Box<dyn Handler>,
+}
+
+// e.g. : /users/:id or /static/*path
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let parts = pattern
+        .split('/')
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<&str>>();
+
+    let mut segments = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        if let Some(name) = part.strip_prefix(':') {
+            segments.push(Segment::Param(name.to_string()));
+        } else if let Some(name) = part.strip_prefix('*') {
+            if i != parts.len() - 1 {
+                panic!("Wildcard must be the last segment of {}", pattern);
+            }
+            segments.push(Segment::Wildcard(name.to_string()));
+        } else {
+            segments.push(Segment::Static(part.to_string()));
+        }
+    }
+
+    segments
+}
+
+fn match_path(pattern: &[Segment], segments: &[String]) -> Option<HashMap<String, String>> {
+    let parts = segments
+        .iter()
+        .map(|s| s.as_str())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<&str>>();
+    let mut params = HashMap::new();
+
+    for (i, segment) in pattern.iter().enumerate() {
+        match segment {
+            Segment::Static(s) => {
+                if parts.get(i) != Some(&s.as_str()) {
+                    return None;
+                }
+            }
+            Segment::Param(name) => {
+                params.insert(name.clone(), parts.get(i)?.to_string());
+            }
+            Segment::Wildcard(name) => {
+                let rest = parts.get(i..).unwrap_or_default().join("/");
+                params.insert(name.clone(), rest);
+                return Some(params);
+            }
+        }
+    }
+
+    if parts.len() != pattern.len() {
+        return None;
+    }
+
+    Some(params)
+}
+
+/// Dispatches requests on method and path. Routes are tried in the order
+/// they were added, and matched path parameters end up in
+/// [`Request::params`]. A `HEAD` request with no route of its own goes to
+/// the matching `GET` route.
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router { routes: Vec::new() }
+    }
+
+    pub fn route<H>(mut self, method: RequestMethod, pattern: &str, handler: H) -> Router
+    where
+        H: Handler,
+    {
+        self.routes.push(Route {
+            method,
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Get, pattern, handler)
+    }
+
+    pub fn post<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Post, pattern, handler)
+    }
+
+    pub fn put<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Put, pattern, handler)
+    }
+
+    pub fn delete<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Delete, pattern, handler)
+    }
+
+    pub fn options<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Options, pattern, handler)
+    }
+
+    pub fn patch<H: Handler>(self, pattern: &str, handler: H) -> Router {
+        self.route(RequestMethod::Patch, pattern, handler)
+    }
+}
+
+impl Default for Router {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Handler for Router {
+    fn call(
+        &self,
+        mut writer: Writer,
+        mut req: Request,
+    ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
+        let mut allowed: Vec<&str> = Vec::new();
+        let mut get_route = None;
+        for route in self.routes.iter() {
+            let params = match match_path(&route.pattern, &req.request_line.uri.segments) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            if route.method == req.request_line.method {
+                req.params = params;
+                return route.handler.call(writer, req);
+            }
+
+            if !allowed.contains(&route.method.as_str()) {
+                allowed.push(route.method.as_str());
+            }
+            // A GET route answers HEAD too, unless HEAD has its own.
+            if route.method == RequestMethod::Get {
+                if !allowed.contains(&"HEAD") {
+                    allowed.push("HEAD");
+                }
+                get_route.get_or_insert((route, params));
+            }
+        }
+
+        if req.request_line.method == RequestMethod::Head
+            && let Some((route, params)) = get_route
+        {
+            req.params = params;
+            return route.handler.call(writer, req);
+        }
+
+        if allowed.is_empty() {
+            return Box::pin(response::reject(writer, StatusCode::NotFound));
+        }
+        // Method names are tokens.
+        writer.set_header("Allow", allowed.join(", ")).unwrap();
+        Box::pin(response::reject(writer, StatusCode::MethodNotAllowed))
+    }
+}
+
+#[cfg(test)]
+mod test;
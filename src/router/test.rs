@@ -0,0 +1,139 @@
+use super::*;
+
+use tokio::io::AsyncWriteExt;
+
+use crate::test_util::call;
+
+async fn echo_params(mut writer: Writer, req: Request) -> Option<HandlerError> {
+    let mut params = req
+        .params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>();
+    params.sort();
+    writer.write_all(params.join("&").as_bytes()).await.unwrap();
+    None
+}
+
+fn router() -> Router {
+    Router::new()
+        .get("/", |mut writer: Writer, _req: Request| async move {
+            writer.write_all(b"index").await.unwrap();
+            None
+        })
+        .get("/users/:id", echo_params)
+        .delete("/users/:id", echo_params)
+        .get("/users/:id/posts/:post", echo_params)
+        .get("/static/*path", echo_params)
+}
+
+#[tokio::test]
+async fn static_route() {
+    let output = call(&router(), b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.ends_with("\r\n\r\nindex"));
+}
+
+#[tokio::test]
+async fn named_params() {
+    let output = call(
+        &router(),
+        b"GET /users/42 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.ends_with("\r\n\r\nid=42"));
+
+    let output = call(
+        &router(),
+        b"GET /users/42/posts/7?draft=1 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.ends_with("\r\n\r\nid=42&post=7"));
+}
+
+#[tokio::test]
+async fn wildcard_param() {
+    let output = call(
+        &router(),
+        b"GET /static/css/site.css HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+
+    assert!(output.ends_with("\r\n\r\npath=css/site.css"));
+}
+
+#[tokio::test]
+async fn unknown_path_is_not_found() {
+    let output = call(&router(), b"GET /users HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+    assert!(output.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    let output = call(
+        &router(),
+        b"GET /users/42/comments HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[tokio::test]
+async fn wrong_method_is_not_allowed() {
+    let output = call(
+        &router(),
+        b"PUT /users/42 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+
+    assert!(output.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    assert!(output.contains("Allow: GET, HEAD, DELETE\r\n"));
+}
+
+#[tokio::test]
+async fn head_prefers_its_own_route_over_get() {
+    let output = call(
+        &router(),
+        b"HEAD /users/42 HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 5\r\n"));
+
+    let route = |name: &'static str| {
+        move |mut writer: Writer, _req: Request| async move {
+            writer.set_header("X-Route", name).unwrap();
+            None
+        }
+    };
+    let router = Router::new()
+        .get("/page", route("get"))
+        .route(RequestMethod::Head, "/page", route("head"))
+        .route(RequestMethod::Head, "/head-only", route("head"));
+
+    let output = call(&router, b"HEAD /page HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+    assert!(output.contains("X-Route: head\r\n"));
+
+    let output = call(
+        &router,
+        b"HEAD /head-only HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("X-Route: head\r\n"));
+}
+
+#[test]
+#[should_panic]
+fn wildcard_must_be_last() {
+    let _ = Router::new().get("/static/*path/more", echo_params);
+}
+
+#[tokio::test]
+async fn params_are_percent_decoded() {
+    let output = call(
+        &router(),
+        b"GET /users/jane%20doe HTTP/1.1\r\nHost: localhost\r\n\r\n",
+    )
+    .await;
+
+    assert!(output.ends_with("\r\n\r\nid=jane doe"));
+}
@@ -0,0 +1,179 @@
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use socket2::{Domain, Socket, Type};
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+
+use crate::request::{Limits, Timeouts};
+use crate::server::{
+    DEFAULT_BODY_TIMEOUT, DEFAULT_GRACE_PERIOD, DEFAULT_HEADER_TIMEOUT, DEFAULT_IDLE_TIMEOUT,
+    Handler, Lifecycle, Server,
+};
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// Configures and starts a [`Server`].
+///
+/// ```no_run
+/// # async fn run() -> std::io::Result<()> {
+/// use httpfromtcp::{Request, Server, Writer};
+///
+/// let server = Server::builder()
+///     .bind("0.0.0.0:8080")
+///     .bind("[::]:8080")
+///     .serve(|_w: Writer, _r: Request| async { None })
+///     .await?;
+/// println!("Listening on {:?}", server.local_addrs());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerBuilder {
+    addrs: Vec<SocketAddr>,
+    listeners: Vec<std::net::TcpListener>,
+    // Resolving an address happens in `bind`, but the error is only
+    // reported by `serve` so calls can be chained.
+    error: Option<Error>,
+    timeouts: Timeouts,
+    grace_period: Duration,
+    limits: Limits,
+}
+
+fn bind_listener(addr: SocketAddr) -> Result<std::net::TcpListener, Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    // Lets an IPv4 and an IPv6 listener share a port.
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    Ok(socket.into())
+}
+
+impl ServerBuilder {
+    pub fn new() -> ServerBuilder {
+        ServerBuilder {
+            addrs: Vec::new(),
+            listeners: Vec::new(),
+            error: None,
+            timeouts: Timeouts {
+                idle: Some(DEFAULT_IDLE_TIMEOUT),
+                header: Some(DEFAULT_HEADER_TIMEOUT),
+                body: Some(DEFAULT_BODY_TIMEOUT),
+            },
+            grace_period: DEFAULT_GRACE_PERIOD,
+            limits: Limits::default(),
+        }
+    }
+
+    /// Listens on every address `addr` resolves to. Use port 0 to let the OS
+    /// pick a free port and read it back from [`Server::local_addrs`].
+    pub fn bind<A: ToSocketAddrs>(mut self, addr: A) -> ServerBuilder {
+        match addr.to_socket_addrs() {
+            Ok(addrs) => self.addrs.extend(addrs),
+            Err(e) => {
+                if self.error.is_none() {
+                    self.error = Some(e);
+                }
+            }
+        }
+        self
+    }
+
+    /// Serves on a listener that is already bound, e.g. one handed over by
+    /// a supervisor.
+    pub fn listener(mut self, listener: std::net::TcpListener) -> ServerBuilder {
+        self.listeners.push(listener);
+        self
+    }
+
+    /// How long a keep-alive connection may sit between requests.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> ServerBuilder {
+        self.timeouts.idle = Some(idle_timeout);
+        self
+    }
+
+    /// How long a client gets to send the request line and headers once it
+    /// has started a request. Running out is answered with `408`.
+    pub fn header_timeout(mut self, header_timeout: Duration) -> ServerBuilder {
+        self.timeouts.header = Some(header_timeout);
+        self
+    }
+
+    /// How long a client can go without sending more of the body, once the
+    /// headers are in. Running out is answered with `408`.
+    pub fn body_timeout(mut self, body_timeout: Duration) -> ServerBuilder {
+        self.timeouts.body = Some(body_timeout);
+        self
+    }
+
+    /// How long [`Server::shutdown`] waits for requests in flight before
+    /// dropping their connections.
+    pub fn grace_period(mut self, grace_period: Duration) -> ServerBuilder {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Caps the size of incoming requests. Going over a limit is answered
+    /// with `414`, `431` or `413` and the connection is closed.
+    pub fn limits(mut self, limits: Limits) -> ServerBuilder {
+        self.limits = limits;
+        self
+    }
+
+    pub async fn serve<H>(self, handler: H) -> Result<Arc<Server>, Error>
+    where
+        H: Handler,
+    {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if self.addrs.is_empty() && self.listeners.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "No Address To Listen On",
+            ));
+        }
+
+        let mut std_listeners = self.listeners;
+        for addr in self.addrs {
+            std_listeners.push(bind_listener(addr)?);
+        }
+
+        let mut listeners = Vec::new();
+        let mut local_addrs = Vec::new();
+        for listener in std_listeners {
+            listener.set_nonblocking(true)?;
+            let listener = TcpListener::from_std(listener)?;
+            local_addrs.push(listener.local_addr()?);
+            listeners.push(listener);
+        }
+
+        let (lifecycle, _) = watch::channel(Lifecycle::Running);
+        let server = Arc::new(Server {
+            handler: Arc::new(handler),
+            local_addrs,
+            lifecycle,
+            timeouts: self.timeouts,
+            grace_period: self.grace_period,
+            limits: self.limits,
+        });
+
+        for listener in listeners {
+            let lifecycle = server.lifecycle.subscribe();
+            tokio::spawn(Server::listen(Arc::clone(&server), listener, lifecycle));
+        }
+
+        Ok(server)
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
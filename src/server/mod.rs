@@ -1,43 +1,68 @@
+use std::net::SocketAddr;
 use std::pin::Pin;
-use std::sync::atomic::Ordering::SeqCst;
-use std::{
-    io::Error,
-    sync::{Arc, atomic::AtomicBool},
-};
-use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::time::Duration;
+use std::{io::Error, sync::Arc};
+use tokio::io::AsyncWriteExt;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+use crate::request::{
+    Body, Connection, Decoder, HttpVersion, Limits, ParseError, Request, RequestMethod,
+    RequestReader, Timeouts,
+};
+use crate::response::{self, ResponseWriter, StatusCode, Stream};
+
+mod builder;
 
-use crate::request::{Request, request_from_reader};
-use crate::response::{self, StatusCode};
+pub use builder::ServerBuilder;
 
 pub trait Handler: Send + Sync + 'static {
     fn call(
         &self,
-        writer: Box<dyn AsyncWrite + Send + Unpin>,
+        writer: Writer,
         req: Request,
     ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>>;
 }
 
 impl<F, Fut> Handler for F
 where
-    F: Fn(Box<dyn AsyncWrite + Send + Unpin>, Request) -> Fut + Send + Sync + 'static,
+    F: Fn(Writer, Request) -> Fut + Send + Sync + 'static,
     Fut: Future<Output = Option<HandlerError>> + Send + 'static,
 {
     fn call(
         &self,
-        writer: Box<dyn AsyncWrite + Send + Unpin>,
+        writer: Writer,
         req: Request,
     ) -> Pin<Box<dyn Future<Output = Option<HandlerError>> + Send>> {
         Box::pin((self)(writer, req))
     }
 }
 
-pub type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+pub type Writer = ResponseWriter;
+
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_BODY_TIMEOUT: Duration = Duration::from_secs(60);
+const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lifecycle {
+    Running,
+    // No new connections or requests, in-flight ones may finish.
+    Draining,
+    // The grace period is over, whatever is left gets dropped.
+    Stopped,
+}
 
 pub struct Server {
     handler: Arc<dyn Handler>,
-    listener: TcpListener,
-    closed: AtomicBool,
+    local_addrs: Vec<SocketAddr>,
+    // Every listener and connection task holds a receiver, so the sender
+    // closing means everything has drained.
+    lifecycle: watch::Sender<Lifecycle>,
+    timeouts: Timeouts,
+    grace_period: Duration,
+    limits: Limits,
 }
 
 pub struct HandlerError {
@@ -46,20 +71,63 @@ pub struct HandlerError {
 }
 
 impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::new()
+    }
+
+    /// The addresses the server is listening on, with the ports the OS
+    /// picked for any bound to port 0.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Stops accepting connections and answering new requests. Requests in
+    /// flight get the grace period to finish, idle keep-alive connections
+    /// are closed right away. The returned future resolves once everything
+    /// has drained.
+    pub fn shutdown(self: &Arc<Self>) -> impl Future<Output = ()> + Send + 'static {
+        self.lifecycle.send_if_modified(|lifecycle| {
+            if *lifecycle != Lifecycle::Running {
+                return false;
+            }
+            *lifecycle = Lifecycle::Draining;
+            true
+        });
+
+        let server = Arc::clone(self);
+        async move {
+            let drained = server.lifecycle.closed();
+            if tokio::time::timeout(server.grace_period, drained)
+                .await
+                .is_err()
+            {
+                server.lifecycle.send_replace(Lifecycle::Stopped);
+                server.lifecycle.closed().await;
+            }
+        }
+    }
+
+    /// Starts a [`shutdown`](Server::shutdown) without waiting for it.
     pub fn close(self: Arc<Self>) {
-        self.closed.store(true, SeqCst);
+        drop(self.shutdown());
     }
 
-    async fn listen(self: Arc<Self>) {
+    async fn listen(
+        self: Arc<Self>,
+        listener: TcpListener,
+        mut lifecycle: watch::Receiver<Lifecycle>,
+    ) {
         loop {
-            if self.closed.load(SeqCst) {
-                break;
-            }
-            match self.listener.accept().await {
+            let accepted = tokio::select! {
+                res = listener.accept() => res,
+                _ = lifecycle.wait_for(|l| *l != Lifecycle::Running) => break,
+            };
+            match accepted {
                 Ok((stream, _)) => {
                     let server = Arc::clone(&self);
+                    let lifecycle = self.lifecycle.subscribe();
                     tokio::spawn(async move {
-                        server.handle(stream).await;
+                        server.handle(stream, lifecycle).await;
                     });
                 }
                 Err(_) => break,
@@ -67,57 +135,189 @@ impl Server {
         }
     }
 
-    async fn handle(self: Arc<Self>, mut stream: TcpStream) {
-        let mut buf: Vec<u8> = Vec::new();
-        let status: StatusCode;
+    async fn handle(self: Arc<Self>, stream: TcpStream, mut lifecycle: watch::Receiver<Lifecycle>) {
+        let peer_addr = stream.peer_addr().ok();
+        let (read_half, write_half) = stream.into_split();
+        let connection: Connection = Box::new(read_half);
+        let mut reader = RequestReader::with_limits(connection, self.limits);
+        reader.set_timeouts(self.timeouts);
+        let mut stream: Stream = Box::new(write_half);
+
+        loop {
+            let next = tokio::select! {
+                res = reader.next_head() => res,
+                _ = lifecycle.wait_for(|l| *l != Lifecycle::Running) => break,
+            };
+            let (mut request, decoder) = match next {
+                Ok(Some(head)) => head,
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Failed to read request: {}", e);
+                    // Nobody is left to answer a closed or broken connection.
+                    if let Some(status_code) = e.status_code() {
+                        let _ = reject(&mut stream, &status_code, &e).await;
+                    }
+                    break;
+                }
+            };
 
-        let request = match request_from_reader(&mut stream).await {
-            Ok(req) => req,
-            Err(e) => {
-                eprintln!("Failed to parse request: {}", e);
-                let _ = response::write_status_line(&mut stream, StatusCode::BadRequest).await;
-                return;
+            request.peer_addr = peer_addr;
+            let keep_alive =
+                *lifecycle.borrow() == Lifecycle::Running && wants_keep_alive(&request);
+
+            let responded = tokio::select! {
+                res = self.respond(stream, reader, request, decoder, keep_alive) => res,
+                _ = lifecycle.wait_for(|l| *l == Lifecycle::Stopped) => return,
+            };
+            match responded {
+                Ok((s, next_reader)) => {
+                    stream = s;
+                    match next_reader {
+                        Some(r) => reader = r,
+                        None => break,
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to write response to stream: {}", e);
+                    return;
+                }
             }
-        };
+        }
+
+        if let Err(e) = stream.shutdown().await {
+            eprintln!("Failed to shutdown stream: {}", e);
+        }
+    }
 
-        let (writer, mut reader) = tokio::io::duplex(4096);
-        let writer_boxed: Box<dyn AsyncWrite + Send + Unpin> = Box::new(writer);
+    // The handler reads the body straight off the connection, which is
+    // handed back once it is done, unless it has to be closed.
+    async fn respond(
+        &self,
+        stream: Stream,
+        reader: RequestReader<Connection>,
+        mut request: Request,
+        decoder: Decoder,
+        keep_alive: bool,
+    ) -> Result<(Stream, Option<RequestReader<Connection>>), Error> {
+        // A HEAD response has its body left off, whatever the handler writes.
+        let head_only = request.request_line.method == RequestMethod::Head;
 
-        // Deadlock
-        let handler_future = (self.handler).call(writer_boxed, request);
-        let read_future = reader.read_to_end(&mut buf);
+        let (writer, response) = ResponseWriter::new(
+            stream,
+            request.request_line.http_version,
+            keep_alive,
+            head_only,
+        );
 
-        let (handler_result, _) = tokio::join!(handler_future, read_future);
+        let interim = expects_continue(&request).then(|| response.interim());
+        let (body, pending_body) = Body::streaming(reader, decoder, interim);
+        request.body = body;
 
-        match handler_result {
-            Some(err) => {
-                status = err.status_code;
-                buf.extend_from_slice(err.message.as_bytes());
-            }
-            None => {
-                status = response::StatusCode::Ok;
+        match (self.handler).call(writer, request).await {
+            Some(err) if response.is_streaming() => {
+                // The status line is already out, all we can do is cut the
+                // response short.
+                drop(response.abort());
+                return Err(Error::other(format!(
+                    "Handler failed mid-stream with {}: {}",
+                    err.status_code.code(),
+                    err.message
+                )));
             }
+            Some(err) => response.fail(err.status_code, &err.message),
+            None => {}
         }
 
-        if let Err(e) = response::write_status_line(&mut stream, status).await {
-            eprintln!("Failed to write status line to stream: {}", e);
-            return;
-        }
+        // A body the handler found to be bad is answered like any other bad
+        // request, if the response hasn't started yet. A client still
+        // waiting on `100 Continue` may or may not send the body now that
+        // it's been answered, so there's no telling where the next request
+        // starts.
+        let unread = match pending_body.error() {
+            Some(e) => {
+                eprintln!("Failed to read request body: {}", e);
+                if let Some(status_code) = e.status_code()
+                    && !response.is_streaming()
+                {
+                    response.fail(status_code, &format!("{}\n", e));
+                }
+                response.close_connection();
+                None
+            }
+            None if pending_body.awaits_continue() => {
+                pending_body.abandon();
+                response.close_connection();
+                None
+            }
+            None => Some(pending_body),
+        };
 
-        let headers = response::get_default_headers(buf.len() as u16);
-        if let Err(e) = response::write_headers(&mut stream, headers).await {
-            eprintln!("Failed to write headers to stream: {}", e);
-            return;
+        if *self.lifecycle.borrow() != Lifecycle::Running {
+            response.close_connection();
         }
 
-        if let Err(e) = stream.write_all(buf.as_slice()).await {
-            eprintln!("Failed to write body to stream: {}", e);
-            return;
-        }
+        let keep_alive = response.keep_alive();
+        let stream = response.finish().await?;
 
-        if let Err(e) = stream.shutdown().await {
-            eprintln!("Failed to shutdown stream: {}", e);
-        }
+        // Whatever the handler left of the body is only read past once the
+        // response is out, so the client isn't kept waiting on a body nobody
+        // wanted. It has to go before the next request can be read, and even
+        // on a connection about to close, unread bytes would have it reset
+        // under the response.
+        let reader = match unread {
+            Some(pending_body) => match pending_body.finish().await {
+                Ok(reader) => Some(reader),
+                Err(e) => {
+                    eprintln!("Failed to read request body: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok((stream, reader.filter(|_| keep_alive)))
+    }
+}
+
+// Answers a request that couldn't be parsed, saying what was wrong with it.
+// The rest of it is never read, so the connection gets closed.
+async fn reject(
+    stream: &mut Stream,
+    status_code: &StatusCode,
+    error: &ParseError,
+) -> Result<(), Error> {
+    let body = format!("{}\n", error);
+    let mut headers = response::get_default_headers(body.len() as u64);
+    headers.insert("Connection", "close");
+
+    response::write_status_line(stream, HttpVersion::Http11, status_code).await?;
+    response::write_headers(stream, headers).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.flush().await
+}
+
+// HTTP/1.0 clients can't ask for `100 Continue`, they don't know it.
+fn expects_continue(request: &Request) -> bool {
+    request.request_line.http_version == HttpVersion::Http11
+        && request
+            .headers
+            .get_joined("expect")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+// HTTP/1.1 connections are persistent unless the client opts out, HTTP/1.0
+// ones only when the client opts in.
+fn wants_keep_alive(request: &Request) -> bool {
+    let has_token = |name: &str| match request.headers.get_joined("connection") {
+        Some(value) => value
+            .split(',')
+            .any(|token| token.trim().eq_ignore_ascii_case(name)),
+        None => false,
+    };
+
+    match request.request_line.http_version {
+        HttpVersion::Http10 => has_token("keep-alive"),
+        HttpVersion::Http11 => !has_token("close"),
     }
 }
 
@@ -125,20 +325,11 @@ pub async fn serve<H>(port: u16, handler: H) -> Result<Arc<Server>, Error>
 where
     H: Handler,
 {
-    let address = format!("127.0.0.1:{}", port);
-    let listener = match TcpListener::bind(address).await {
-        Ok(res) => res,
-        Err(e) => return Err(e),
-    };
-
-    let server = Arc::new(Server {
-        handler: Arc::new(handler),
-        listener,
-        closed: AtomicBool::new(false),
-    });
-
-    let server_clone = Arc::clone(&server);
-    tokio::spawn(Server::listen(server_clone));
-
-    Ok(server)
+    Server::builder()
+        .bind(("127.0.0.1", port))
+        .serve(handler)
+        .await
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,780 @@
+use super::*;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::test_util::{self, echo, hello, read_response, response_body, status_for};
+
+// Reads the whole body first, leaving whatever went wrong to the server.
+async fn hello_after_body(writer: Writer, mut req: Request) -> Option<HandlerError> {
+    let _ = req.body.collect().await;
+    hello(writer, req).await
+}
+
+async fn start() -> Arc<Server> {
+    test_util::start(hello).await
+}
+
+#[tokio::test]
+async fn port_zero_reports_bound_address() {
+    let server = start().await;
+
+    let addrs = server.local_addrs();
+    assert_eq!(addrs.len(), 1);
+    assert!(addrs[0].ip().is_loopback());
+    assert_ne!(addrs[0].port(), 0);
+}
+
+#[tokio::test]
+async fn keep_alive_serves_several_requests() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET /one HTTP/1.1\r\nHost: test\r\n\r\n")
+        .await
+        .unwrap();
+    let first = read_response(&mut stream).await;
+    assert!(first.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(first.ends_with("Hello from /one"));
+
+    // Pipelined: both requests arrive before the first response is read.
+    stream
+        .write_all(b"GET /two HTTP/1.1\r\nHost: localhost\r\n\r\nGET /three HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let second = read_response(&mut stream).await;
+    assert!(second.ends_with("Hello from /two"));
+    let third = read_response(&mut stream).await;
+    assert!(third.contains("Connection: close\r\n"));
+    assert!(third.ends_with("Hello from /three"));
+
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn head_request_has_no_body() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"HEAD /page HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut output = String::new();
+    stream.read_to_string(&mut output).await.unwrap();
+
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 16\r\n"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn handlers_can_skip_the_body_for_head() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(|mut writer: Writer, req: Request| async move {
+            assert_eq!(req.request_line.method, RequestMethod::Head);
+            writer.start_with_length(1 << 30).await.unwrap();
+            if !writer.is_head() {
+                writer.write_all(b"not all of it").await.unwrap();
+            }
+            None
+        })
+        .await
+        .unwrap();
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"HEAD / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut output = String::new();
+    stream.read_to_string(&mut output).await.unwrap();
+
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.contains("Content-Length: 1073741824\r\n"));
+    assert!(output.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn unsupported_version_gets_505() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream.write_all(b"GET / HTTP/2.0\r\n\r\n").await.unwrap();
+    let mut output = String::new();
+    stream.read_to_string(&mut output).await.unwrap();
+
+    assert!(output.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+}
+
+#[tokio::test]
+async fn oversized_requests_are_rejected() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .limits(Limits {
+            max_request_line: 64,
+            max_header_size: 128,
+            max_header_count: 4,
+            max_body_size: 16,
+        })
+        .serve(hello)
+        .await
+        .expect("Cannot start server");
+
+    let long_target = format!(
+        "GET /{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "a".repeat(100)
+    );
+    assert_eq!(
+        status_for(&server, long_target.as_bytes()).await,
+        "HTTP/1.1 414 URI Too Long"
+    );
+
+    let big_header = format!(
+        "GET / HTTP/1.1\r\nHost: localhost\r\nCookie: {}\r\n\r\n",
+        "a".repeat(200)
+    );
+    assert_eq!(
+        status_for(&server, big_header.as_bytes()).await,
+        "HTTP/1.1 431 Request Header Fields Too Large"
+    );
+
+    let many_headers =
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n";
+    assert_eq!(
+        status_for(&server, many_headers).await,
+        "HTTP/1.1 431 Request Header Fields Too Large"
+    );
+
+    let big_body = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000\r\n\r\n";
+    assert_eq!(
+        status_for(&server, big_body).await,
+        "HTTP/1.1 413 Content Too Large"
+    );
+
+    let fits = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello";
+    assert_eq!(status_for(&server, fits).await, "HTTP/1.1 200 OK");
+}
+
+#[tokio::test]
+async fn slow_requests_get_408() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .header_timeout(Duration::from_millis(100))
+        .body_timeout(Duration::from_millis(100))
+        .serve(hello_after_body)
+        .await
+        .expect("Cannot start server");
+
+    let partial_headers = b"GET / HTTP/1.1\r\nHost: local";
+    assert_eq!(
+        status_for(&server, partial_headers).await,
+        "HTTP/1.1 408 Request Timeout"
+    );
+
+    let partial_body = b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nHello";
+    assert_eq!(
+        status_for(&server, partial_body).await,
+        "HTTP/1.1 408 Request Timeout"
+    );
+}
+
+#[tokio::test]
+async fn slow_but_steady_bodies_are_read() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .body_timeout(Duration::from_millis(300))
+        .serve(|writer: Writer, req: Request| async move {
+            // Not the client's time to spend.
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            hello_after_body(writer, req).await
+        })
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"POST /slow HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    for byte in b"Hello" {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stream.write_all(&[*byte]).await.unwrap();
+    }
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /slow")
+    );
+}
+
+#[tokio::test]
+async fn idle_connection_is_closed_quietly() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .idle_timeout(Duration::from_millis(100))
+        .serve(hello)
+        .await
+        .expect("Cannot start server");
+
+    assert_eq!(status_for(&server, b"").await, "");
+}
+
+#[tokio::test]
+async fn idle_timeout_ends_once_a_request_starts() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .idle_timeout(Duration::from_millis(100))
+        .header_timeout(Duration::from_secs(5))
+        .serve(hello)
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    // Slower than the idle timeout, but the request had started.
+    stream.write_all(b"GET /slow HTTP/1.1\r\n").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    stream.write_all(b"Host: test\r\n\r\n").await.unwrap();
+    let first = read_response(&mut stream).await;
+    assert!(first.ends_with("Hello from /slow"));
+
+    // Kept alive for a second request within the idle timeout.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    stream
+        .write_all(b"GET /again HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let second = read_response(&mut stream).await;
+    assert!(second.ends_with("Hello from /again"));
+
+    // Then closed once idle for longer.
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn parse_errors_explain_themselves() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nBad Header: value\r\n\r\n")
+        .await
+        .unwrap();
+    let output = read_response(&mut stream).await;
+
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(output.ends_with("\r\n\r\nMalformed Header at byte 16\n"));
+
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+    stream
+        .write_all(b"FE(TCH) / HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let output = read_response(&mut stream).await;
+
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.ends_with("\r\n\r\nInvalid Request Method at byte 0\n"));
+}
+
+#[tokio::test]
+async fn smuggled_requests_never_reach_the_handler() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\nHost: a\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut output = String::new();
+    stream.read_to_string(&mut output).await.unwrap();
+
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+}
+
+// Reads until `end` shows up in what the server sent.
+async fn read_until(stream: &mut TcpStream, output: &mut Vec<u8>, end: &[u8]) {
+    let mut buf = [0u8; 256];
+    while !output.windows(end.len()).any(|w| w == end) {
+        let n = stream.read(&mut buf).await.unwrap();
+        assert_ne!(n, 0, "Connection closed early");
+        output.extend_from_slice(&buf[..n]);
+    }
+}
+
+#[tokio::test]
+async fn handlers_read_the_body_as_it_arrives() {
+    let server = test_util::start(echo).await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"POST /chunked HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nHello")
+        .await
+        .unwrap();
+    let mut output = Vec::new();
+    read_until(&mut stream, &mut output, b"\r\n5\r\nHello\r\n").await;
+
+    stream.write_all(b" world").await.unwrap();
+    read_until(&mut stream, &mut output, b"abc123\r\n\r\n").await;
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(
+        output.ends_with(
+            "\r\n5\r\nHello\r\n6\r\n world\r\n1\r\n\n\r\n0\r\nX-Checksum: abc123\r\n\r\n"
+        )
+    );
+}
+
+#[tokio::test]
+async fn unread_bodies_are_skipped() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(
+            b"POST /one HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nHello\
+              POST /two HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nHello\r\n0\r\n\r\n\
+              GET /three HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await
+        .unwrap();
+
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /one")
+    );
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /two")
+    );
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /three")
+    );
+}
+
+#[tokio::test]
+async fn bad_bodies_are_answered_like_bad_requests() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .limits(Limits {
+            max_body_size: 16,
+            ..Limits::default()
+        })
+        .serve(hello_after_body)
+        .await
+        .expect("Cannot start server");
+
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+    stream
+        .write_all(
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        )
+        .await
+        .unwrap();
+    let output = read_response(&mut stream).await;
+    assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(output.ends_with("\r\n\r\nMalformed Chunk at byte 64\n"));
+
+    assert_eq!(
+        status_for(
+            &server,
+            b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n0123456789abcdef\r\n1\r\n!\r\n0\r\n\r\n"
+        )
+        .await,
+        "HTTP/1.1 413 Content Too Large"
+    );
+}
+
+#[tokio::test]
+async fn unread_bodies_are_read_past_after_the_response() {
+    let server = start().await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"POST /one HTTP/1.1\r\nHost: localhost\r\nContent-Length: 10\r\n\r\nHello")
+        .await
+        .unwrap();
+    let output = tokio::time::timeout(Duration::from_secs(1), read_response(&mut stream))
+        .await
+        .expect("Response held back by the unread body");
+    assert!(output.ends_with("Hello from /one"));
+
+    stream
+        .write_all(b"WorldGET /two HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /two")
+    );
+
+    // Found bad only after the response went out, so all that's left to do
+    // is to close the connection.
+    stream
+        .write_all(
+            b"POST /three HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        )
+        .await
+        .unwrap();
+    let output = read_response(&mut stream).await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
+
+async fn upload(mut writer: Writer, mut req: Request) -> Option<HandlerError> {
+    if req.headers.get("authorization").is_none() {
+        return Some(HandlerError {
+            status_code: StatusCode::Unauthorized,
+            message: "Who are you?".to_string(),
+        });
+    }
+
+    let body = req.body.collect().await.unwrap();
+    writer
+        .write_all(format!("Got {} bytes", body.len()).as_bytes())
+        .await
+        .unwrap();
+    None
+}
+
+#[tokio::test]
+async fn continue_is_sent_when_the_body_is_read() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(upload)
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"PUT /file HTTP/1.1\r\nHost: localhost\r\nAuthorization: yes\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n")
+        .await
+        .unwrap();
+    let mut interim = [0u8; 25];
+    stream.read_exact(&mut interim).await.unwrap();
+    assert_eq!(&interim, b"HTTP/1.1 100 Continue\r\n\r\n");
+
+    stream.write_all(b"Hello").await.unwrap();
+    let output = read_response(&mut stream).await;
+    assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(output.ends_with("Got 5 bytes"));
+
+    // The connection is still good for another request.
+    stream
+        .write_all(b"PUT /file HTTP/1.1\r\nHost: localhost\r\nAuthorization: yes\r\nContent-Length: 2\r\n\r\nHi")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream).await.ends_with("Got 2 bytes"));
+}
+
+#[tokio::test]
+async fn handlers_can_refuse_before_the_upload() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(upload)
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"PUT /file HTTP/1.1\r\nHost: localhost\r\nExpect: 100-continue\r\nContent-Length: 100000\r\n\r\n")
+        .await
+        .unwrap();
+    let mut output = String::new();
+    stream.read_to_string(&mut output).await.unwrap();
+
+    assert!(output.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+    assert!(output.contains("Connection: close\r\n"));
+    assert!(output.ends_with("Who are you?"));
+}
+
+const LARGE_RESPONSE: usize = 8 * 1024 * 1024;
+
+// Streams the body chunked, sized up front, or buffers all of it.
+async fn large(mut writer: Writer, req: Request) -> Option<HandlerError> {
+    match req.request_line.uri.path.as_str() {
+        "/chunked" => writer.start_chunked().await.unwrap(),
+        "/sized" => writer
+            .start_with_length(LARGE_RESPONSE as u64)
+            .await
+            .unwrap(),
+        _ => {}
+    }
+    let piece = (0..=255).cycle().take(64 * 1024).collect::<Vec<u8>>();
+    for _ in 0..LARGE_RESPONSE / piece.len() {
+        writer.write_all(&piece).await.unwrap();
+    }
+    None
+}
+
+#[tokio::test]
+async fn multi_megabyte_responses() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(large)
+        .await
+        .expect("Cannot start server");
+
+    for request in [
+        &b"GET /chunked HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"[..],
+        &b"GET /chunked HTTP/1.0\r\n\r\n"[..],
+        &b"GET /sized HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"[..],
+        &b"GET /buffered HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"[..],
+        &b"GET /buffered HTTP/1.0\r\n\r\n"[..],
+    ] {
+        let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+        stream.write_all(request).await.unwrap();
+        // Let the handler run into a full socket before anything is read.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut output = Vec::new();
+        stream.read_to_end(&mut output).await.unwrap();
+
+        let head = String::from_utf8_lossy(&output[..output.len().min(1024)]);
+        if !request.starts_with(b"GET /chunked") {
+            assert!(head.contains(&format!("Content-Length: {}\r\n", LARGE_RESPONSE)));
+        }
+        let body = response_body(&output);
+        assert_eq!(body.len(), LARGE_RESPONSE);
+        assert!(body.iter().zip((0..=255).cycle()).all(|(b, i)| *b == i));
+    }
+}
+
+#[tokio::test]
+async fn short_declared_length_closes_the_connection() {
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(|mut writer: Writer, _req: Request| async move {
+            writer.start_with_length(13).await.unwrap();
+            writer.write_all(b"Hello").await.unwrap();
+            None
+        })
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nGET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).await.unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains("Content-Length: 13\r\n"));
+    assert!(output.ends_with("\r\n\r\nHello"));
+}
+
+#[tokio::test]
+async fn writers_kept_past_the_handler_are_detached() {
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(move |mut writer: Writer, _req: Request| {
+            let sender = sender.clone();
+            async move {
+                writer.write_all(b"Hello").await.unwrap();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    sender.send(writer.write_all(b" again").await).unwrap();
+                });
+                None
+            }
+        })
+        .await
+        .expect("Cannot start server");
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    let output = read_response(&mut stream).await;
+    assert!(output.ends_with("\r\n\r\nHello"));
+
+    let late_write = receiver.recv().await.unwrap();
+    assert_eq!(
+        late_write.unwrap_err().kind(),
+        std::io::ErrorKind::BrokenPipe
+    );
+}
+
+#[tokio::test]
+async fn adopts_bound_listener() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let server = Server::builder()
+        .listener(listener)
+        .serve(hello)
+        .await
+        .expect("Cannot start server");
+    assert_eq!(server.local_addrs(), &[addr]);
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /adopted HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(
+        read_response(&mut stream)
+            .await
+            .ends_with("Hello from /adopted")
+    );
+}
+
+#[tokio::test]
+async fn listens_on_several_addresses() {
+    // Skip on hosts without IPv6.
+    if std::net::TcpListener::bind("[::1]:0").is_err() {
+        return;
+    }
+
+    let server = Server::builder()
+        .bind("127.0.0.1:0")
+        .bind("[::1]:0")
+        .serve(hello)
+        .await
+        .expect("Cannot start server");
+
+    let addrs = server.local_addrs();
+    assert_eq!(addrs.len(), 2);
+    assert!(addrs[0].is_ipv4());
+    assert!(addrs[1].is_ipv6());
+
+    for addr in addrs {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        assert!(read_response(&mut stream).await.ends_with("Hello from /"));
+    }
+}
+
+#[tokio::test]
+async fn bind_errors_surface_on_serve() {
+    let result = Server::builder().bind("not an address").serve(hello).await;
+    assert!(result.is_err());
+
+    let result = Server::builder().serve(hello).await;
+    assert!(result.is_err());
+}
+
+async fn slow(mut writer: Writer, req: Request) -> Option<HandlerError> {
+    let millis = req
+        .request_line
+        .uri
+        .query
+        .get("ms")
+        .and_then(|ms| ms.parse::<u64>().ok())
+        .unwrap_or(0);
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+    writer.write_all(b"done").await.unwrap();
+    None
+}
+
+async fn start_slow(grace_period: Duration) -> Arc<Server> {
+    Server::builder()
+        .bind("127.0.0.1:0")
+        .grace_period(grace_period)
+        .serve(slow)
+        .await
+        .expect("Cannot start server")
+}
+
+#[tokio::test]
+async fn shutdown_stops_accepting() {
+    let server = start_slow(Duration::from_secs(5)).await;
+    let addr = server.local_addrs()[0];
+
+    tokio::time::timeout(Duration::from_secs(1), server.shutdown())
+        .await
+        .expect("Shutdown did not drain");
+
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[tokio::test]
+async fn shutdown_lets_in_flight_requests_finish() {
+    let server = start_slow(Duration::from_secs(5)).await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET /?ms=300 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let shutdown = tokio::spawn(server.shutdown());
+
+    let response = read_response(&mut stream).await;
+    assert!(response.contains("Connection: close\r\n"));
+    assert!(response.ends_with("done"));
+
+    tokio::time::timeout(Duration::from_secs(1), shutdown)
+        .await
+        .expect("Shutdown did not drain")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn shutdown_closes_idle_connections() {
+    let server = start_slow(Duration::from_secs(5)).await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    assert!(read_response(&mut stream).await.ends_with("done"));
+
+    tokio::time::timeout(Duration::from_secs(1), server.shutdown())
+        .await
+        .expect("Shutdown did not drain");
+
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn shutdown_drops_requests_after_grace_period() {
+    let server = start_slow(Duration::from_millis(100)).await;
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+
+    stream
+        .write_all(b"GET /?ms=10000 HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    tokio::time::timeout(Duration::from_secs(1), server.shutdown())
+        .await
+        .expect("Shutdown did not give up after the grace period");
+
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
@@ -0,0 +1,150 @@
+// Servers to test against, raw ways to talk to them and handlers to put
+// behind them, shared by the tests of every module.
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::request::{Request, RequestMethod, request_from_reader};
+use crate::response::ResponseWriter;
+use crate::server::{Handler, HandlerError, Server, Writer};
+
+pub(crate) async fn start<H: Handler>(handler: H) -> Arc<Server> {
+    Server::builder()
+        .bind("127.0.0.1:0")
+        .serve(handler)
+        .await
+        .expect("Cannot start server")
+}
+
+// Sends `request` on a new connection and reads until the server closes it.
+pub(crate) async fn send(server: &Server, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(server.local_addrs()[0]).await.unwrap();
+    stream.write_all(request).await.unwrap();
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output).await.unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+// Sends `request` and returns just the status line, or nothing if the
+// server closed the connection without answering.
+pub(crate) async fn status_for(server: &Server, request: &[u8]) -> String {
+    let output = send(server, request).await;
+    output.lines().next().unwrap_or_default().to_string()
+}
+
+// Runs `handler` on `request` in-process, with the writer set up the way
+// the server would, and returns what it sent.
+pub(crate) async fn call<H: Handler>(handler: &H, request: &[u8]) -> String {
+    let request = request_from_reader(request)
+        .await
+        .expect("Failed to parse request");
+    let head_only = request.request_line.method == RequestMethod::Head;
+
+    let (mut client, server) = tokio::io::duplex(64 * 1024);
+    let reading = tokio::spawn(async move {
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        String::from_utf8(buf).unwrap()
+    });
+    let (writer, response) = ResponseWriter::new(
+        Box::new(server),
+        request.request_line.http_version,
+        true,
+        head_only,
+    );
+    if let Some(err) = handler.call(writer, request).await {
+        response.fail(err.status_code, &err.message);
+    }
+    drop(response.finish().await.unwrap());
+
+    reading.await.unwrap()
+}
+
+// Reads one response framed by Content-Length.
+pub(crate) async fn read_response(stream: &mut TcpStream) -> String {
+    let mut buf = Vec::new();
+    let mut byte = [0u8; 1];
+    while !buf.ends_with(b"\r\n\r\n") {
+        let n = stream.read(&mut byte).await.unwrap();
+        assert_eq!(n, 1, "Connection closed mid-head");
+        buf.push(byte[0]);
+    }
+
+    let head = String::from_utf8(buf.clone()).unwrap();
+    let content_length = head
+        .lines()
+        .find_map(|l| l.strip_prefix("Content-Length: "))
+        .map(|l| l.parse::<usize>().unwrap())
+        .unwrap_or(0);
+
+    let mut body = vec![0u8; content_length];
+    stream.read_exact(&mut body).await.unwrap();
+    buf.extend_from_slice(&body);
+
+    String::from_utf8(buf).unwrap()
+}
+
+// Takes the head off a whole response and decodes a chunked body.
+pub(crate) fn response_body(output: &[u8]) -> Vec<u8> {
+    let head_end = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let head = String::from_utf8_lossy(&output[..head_end]);
+    let mut rest = &output[head_end..];
+    if !head.contains("Transfer-Encoding: chunked\r\n") {
+        return rest.to_vec();
+    }
+
+    let mut body = Vec::new();
+    loop {
+        let line_end = rest.windows(2).position(|w| w == b"\r\n").unwrap();
+        let size = std::str::from_utf8(&rest[..line_end]).unwrap();
+        let size = usize::from_str_radix(size, 16).unwrap();
+        rest = &rest[line_end + 2..];
+        if size == 0 {
+            return body;
+        }
+        body.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+    }
+}
+
+pub(crate) async fn hello(mut writer: Writer, req: Request) -> Option<HandlerError> {
+    let body = format!("Hello from {}", req.request_line.uri.path);
+    writer.write_all(body.as_bytes()).await.unwrap();
+    None
+}
+
+// Sends back the request as the server saw it: the method and target, the
+// headers, the body and the trailers, a line each. The port it came from is
+// in `X-Peer-Port`. `/chunked` answers with a chunked response, passing the
+// body on as it arrives, and a trailer of its own.
+pub(crate) async fn echo(mut writer: Writer, mut req: Request) -> Option<HandlerError> {
+    let port = req.peer_addr.unwrap().port().to_string();
+    writer.set_header("X-Peer-Port", port).unwrap();
+    if req.request_line.uri.path == "/chunked" {
+        writer.start_chunked().await.unwrap();
+        writer.set_trailer("X-Checksum", "abc123").unwrap();
+    }
+
+    let mut seen = format!(
+        "{} {}\n",
+        req.request_line.method.as_str(),
+        req.request_line.request_target
+    );
+    for (key, value) in req.headers.iter() {
+        seen.push_str(&format!("{}: {}\n", key, String::from_utf8_lossy(value)));
+    }
+    seen.push('\n');
+    writer.write_all(seen.as_bytes()).await.unwrap();
+
+    while let Some(data) = req.body.chunk().await.unwrap() {
+        writer.write_all(&data).await.unwrap();
+    }
+    let mut seen = "\n".to_string();
+    for (key, value) in req.body.trailers().iter() {
+        seen.push_str(&format!("{}: {}\n", key, String::from_utf8_lossy(value)));
+    }
+    writer.write_all(seen.as_bytes()).await.unwrap();
+    None
+}
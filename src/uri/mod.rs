@@ -0,0 +1,236 @@
+use std::io::Error;
+
+/// The four shapes a request target can take (RFC 9112 section 3.2).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TargetForm {
+    /// `/path?query`, what almost every request uses.
+    #[default]
+    Origin,
+    /// `http://host/path?query`, sent to proxies.
+    Absolute,
+    /// `host:port`, only used by CONNECT.
+    Authority,
+    /// `*`, only used by OPTIONS.
+    Asterisk,
+}
+
+/// Query parameters in the order they were sent. A key can appear more than
+/// once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+}
+
+impl Query {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.pairs.iter().any(|(k, _)| k == key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// A parsed request target with its path and query percent-decoded. The raw
+/// target stays available as [`RequestLine::request_target`].
+///
+/// [`RequestLine::request_target`]: crate::request::RequestLine::request_target
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Uri {
+    pub form: TargetForm,
+    pub scheme: Option<String>,
+    pub authority: Option<String>,
+    pub path: String,
+    /// The path split on `/`, each segment decoded on its own so an encoded
+    /// `%2F` stays inside its segment.
+    pub segments: Vec<String>,
+    pub query: Query,
+}
+
+fn error_malformed_uri() -> Error {
+    Error::new(std::io::ErrorKind::InvalidData, "Malformed Request Target")
+}
+
+fn from_hex(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+pub fn percent_decode(input: &str) -> Result<String, Error> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let high = bytes.get(i + 1).and_then(|b| from_hex(*b));
+        let low = bytes.get(i + 2).and_then(|b| from_hex(*b));
+        match (high, low) {
+            (Some(h), Some(l)) => decoded.push(h << 4 | l),
+            _ => return Err(error_malformed_uri()),
+        }
+        i += 3;
+    }
+
+    match String::from_utf8(decoded) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(error_malformed_uri()),
+    }
+}
+
+// e.g. : a=1&b=two%20words&flag
+fn parse_query(query: &str) -> Result<Query, Error> {
+    let mut pairs = Vec::new();
+
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        // Forms encode spaces as '+'.
+        let key = percent_decode(&key.replace('+', " "))?;
+        let value = percent_decode(&value.replace('+', " "))?;
+        pairs.push((key, value));
+    }
+
+    Ok(Query { pairs })
+}
+
+fn is_scheme(scheme: &str) -> bool {
+    let mut chars = scheme.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+
+    chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+// e.g. : example.com:443
+fn is_authority(authority: &str) -> bool {
+    !authority.is_empty() && !authority.contains(['/', '?', '@'])
+}
+
+// The path and query of an absolute-form target.
+// e.g. : http://example.com/coffee?size=large
+pub(crate) fn origin_form(target: &str) -> String {
+    let rest = target.split_once("://").map_or("", |(_, rest)| rest);
+    match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => format!("/{}", &rest[i..]),
+        Some(i) => rest[i..].to_string(),
+        None => "/".to_string(),
+    }
+}
+
+impl Uri {
+    pub fn parse(target: &str) -> Result<Uri, Error> {
+        // Only visible ASCII is allowed, and fragments are never sent.
+        if target.is_empty() || !target.bytes().all(|b| b.is_ascii_graphic()) {
+            return Err(error_malformed_uri());
+        }
+        if target.contains('#') {
+            return Err(error_malformed_uri());
+        }
+
+        if target == "*" {
+            return Ok(Uri {
+                form: TargetForm::Asterisk,
+                ..Uri::default()
+            });
+        }
+
+        if target.starts_with('/') {
+            return Uri::parse_path_and_query(TargetForm::Origin, target);
+        }
+
+        if let Some((scheme, rest)) = target.split_once("://") {
+            if !is_scheme(scheme) {
+                return Err(error_malformed_uri());
+            }
+
+            let end = rest.find(['/', '?']).unwrap_or(rest.len());
+            let authority = &rest[..end];
+            if !is_authority(authority) {
+                return Err(error_malformed_uri());
+            }
+
+            let path_and_query = match &rest[end..] {
+                "" => "/".to_string(),
+                p if p.starts_with('?') => format!("/{}", p),
+                p => p.to_string(),
+            };
+
+            let mut uri = Uri::parse_path_and_query(TargetForm::Absolute, &path_and_query)?;
+            uri.scheme = Some(scheme.to_ascii_lowercase());
+            uri.authority = Some(authority.to_string());
+            return Ok(uri);
+        }
+
+        match target.rsplit_once(':') {
+            Some((host, port))
+                if is_authority(host)
+                    && !port.is_empty()
+                    && port.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                Ok(Uri {
+                    form: TargetForm::Authority,
+                    authority: Some(target.to_string()),
+                    ..Uri::default()
+                })
+            }
+            _ => Err(error_malformed_uri()),
+        }
+    }
+
+    fn parse_path_and_query(form: TargetForm, target: &str) -> Result<Uri, Error> {
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target, ""),
+        };
+
+        let mut segments = Vec::new();
+        for segment in path.split('/').skip(1) {
+            segments.push(percent_decode(segment)?);
+        }
+
+        Ok(Uri {
+            form,
+            path: percent_decode(path)?,
+            segments,
+            query: parse_query(query)?,
+            ..Uri::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test;
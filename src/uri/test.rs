@@ -0,0 +1,89 @@
+use super::*;
+
+#[test]
+fn origin_form() {
+    let uri = Uri::parse("/search/books?q=rust+lang&tag=a&tag=b%26c&flag").unwrap();
+
+    assert_eq!(uri.form, TargetForm::Origin);
+    assert_eq!(uri.path, "/search/books");
+    assert_eq!(uri.segments, vec!["search", "books"]);
+    assert_eq!(uri.query.get("q"), Some("rust lang"));
+    assert_eq!(uri.query.get_all("tag"), vec!["a", "b&c"]);
+    assert_eq!(uri.query.get("flag"), Some(""));
+    assert_eq!(uri.query.get("missing"), None);
+    assert_eq!(uri.query.len(), 4);
+    assert_eq!(uri.scheme, None);
+    assert_eq!(uri.authority, None);
+}
+
+#[test]
+fn root_path() {
+    let uri = Uri::parse("/").unwrap();
+
+    assert_eq!(uri.path, "/");
+    assert_eq!(uri.segments, vec![""]);
+    assert!(uri.query.is_empty());
+}
+
+#[test]
+fn percent_decoded_segments() {
+    let uri = Uri::parse("/files/a%2Fb/caf%C3%A9").unwrap();
+
+    assert_eq!(uri.path, "/files/a/b/café");
+    assert_eq!(uri.segments, vec!["files", "a/b", "café"]);
+}
+
+#[test]
+fn absolute_form() {
+    let uri = Uri::parse("HTTP://example.com:8080/index.html?x=1").unwrap();
+
+    assert_eq!(uri.form, TargetForm::Absolute);
+    assert_eq!(uri.scheme.as_deref(), Some("http"));
+    assert_eq!(uri.authority.as_deref(), Some("example.com:8080"));
+    assert_eq!(uri.path, "/index.html");
+    assert_eq!(uri.query.get("x"), Some("1"));
+
+    let uri = Uri::parse("http://example.com").unwrap();
+    assert_eq!(uri.path, "/");
+}
+
+#[test]
+fn authority_form() {
+    let uri = Uri::parse("example.com:443").unwrap();
+    assert_eq!(uri.form, TargetForm::Authority);
+    assert_eq!(uri.authority.as_deref(), Some("example.com:443"));
+
+    let uri = Uri::parse("[::1]:8443").unwrap();
+    assert_eq!(uri.authority.as_deref(), Some("[::1]:8443"));
+}
+
+#[test]
+fn asterisk_form() {
+    let uri = Uri::parse("*").unwrap();
+
+    assert_eq!(uri.form, TargetForm::Asterisk);
+    assert!(uri.segments.is_empty());
+}
+
+#[test]
+fn malformed_percent_encoding() {
+    for target in ["/bad%", "/bad%2", "/bad%zz", "/ok?q=%G1", "/%FF%FE"] {
+        assert!(Uri::parse(target).is_err(), "{} should be rejected", target);
+    }
+}
+
+#[test]
+fn malformed_targets() {
+    for target in [
+        "",
+        "coffee",
+        "/with#fragment",
+        "/caf\u{e9}",
+        "1http://example.com/",
+        "http:///path",
+        "example.com:",
+        "example.com:https",
+    ] {
+        assert!(Uri::parse(target).is_err(), "{} should be rejected", target);
+    }
+}